@@ -0,0 +1,461 @@
+use crate::config::Arena;
+use crate::simulation::{FoodKind, PendingTurns, Simulation, Steer, Turn};
+use crate::{Direction, Position};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Right,
+    Direction::Down,
+    Direction::Left,
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Strategy {
+    /// Heads for the nearest food as the crow flies, only avoiding immediate collisions, or
+    /// the roomiest direction if there is no food.
+    Greedy,
+    /// Follows the shortest free path to the nearest food, or the roomiest direction if
+    /// there is none.
+    Pathfinder,
+    /// Follows a fixed cycle through every tile of an empty board, which never runs into
+    /// itself.
+    Hamiltonian,
+}
+
+impl Strategy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::Greedy => "greedy",
+            Strategy::Pathfinder => "pathfinder",
+            Strategy::Hamiltonian => "hamiltonian",
+        }
+    }
+}
+
+/// Who decides where a snake turns next.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SnakeController {
+    /// A local player, by index into the per-player bindings.
+    Player(usize),
+    Bot(Strategy),
+}
+
+/// What a bot sees of the board when choosing its next move.
+pub struct Board {
+    pub arena: Arena,
+    pub blocked: HashSet<Position>,
+    /// Heads of the living snakes, next to which a rival may move on the same tick.
+    pub heads: Vec<Position>,
+    pub food: Vec<Position>,
+}
+
+impl Board {
+    fn is_free(&self, position: Position) -> bool {
+        !self.blocked.contains(&position)
+    }
+
+    fn step(&self, position: Position, direction: Direction) -> Option<Position> {
+        position
+            .do_move(direction, self.arena)
+            .filter(|next| self.is_free(*next))
+    }
+
+    /// Whether a rival's head could move onto a position on the same tick.
+    fn near_rival(&self, head: Position, position: Position) -> bool {
+        self.heads
+            .iter()
+            .filter(|rival| **rival != head)
+            .any(|rival| {
+                DIRECTIONS
+                    .into_iter()
+                    .any(|direction| rival.do_move(direction, self.arena) == Some(position))
+            })
+    }
+
+    /// Directions the snake can take without dying on the next move, leaving out those a
+    /// rival's head could also reach unless there is nothing else.
+    fn safe_directions(&self, head: Position, heading: Direction) -> Vec<(Direction, Position)> {
+        let moves: Vec<_> = DIRECTIONS
+            .into_iter()
+            .filter(|direction| *direction != heading.opposite())
+            .filter_map(|direction| {
+                self.step(head, direction)
+                    .map(|position| (direction, position))
+            })
+            .collect();
+        let calm: Vec<_> = moves
+            .iter()
+            .copied()
+            .filter(|(_, position)| !self.near_rival(head, *position))
+            .collect();
+        if calm.is_empty() {
+            moves
+        } else {
+            calm
+        }
+    }
+
+    fn distance(&self, from: Position, to: Position) -> i32 {
+        let (wrap_x, wrap_y) = self.arena.boundary.wraps();
+        let axis = |from: i32, to: i32, size: u32, wraps: bool| {
+            let distance = (from - to).abs();
+            if wraps {
+                distance.min(size as i32 - distance)
+            } else {
+                distance
+            }
+        };
+        axis(from.x, to.x, self.arena.width, wrap_x) + axis(from.y, to.y, self.arena.height, wrap_y)
+    }
+
+    /// Number of free tiles reachable from a position.
+    fn room(&self, memory: &mut BotMemory, start: Position) -> usize {
+        let BotMemory { seen, queue, .. } = memory;
+        seen.clear();
+        seen.insert(start);
+        queue.clear();
+        queue.push_back(start);
+        while let Some(position) = queue.pop_front() {
+            for direction in DIRECTIONS {
+                if let Some(next) = self.step(position, direction) {
+                    if seen.insert(next) {
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        seen.len()
+    }
+
+    /// First move of a shortest path to the nearest food, found by breadth-first search.
+    fn path_to_food(
+        &self,
+        memory: &mut BotMemory,
+        head: Position,
+        heading: Direction,
+    ) -> Option<Direction> {
+        let BotMemory {
+            first_moves, queue, ..
+        } = memory;
+        first_moves.clear();
+        queue.clear();
+        for (direction, position) in self.safe_directions(head, heading) {
+            first_moves.insert(position, direction);
+            queue.push_back(position);
+        }
+        while let Some(position) = queue.pop_front() {
+            let first_move = first_moves[&position];
+            if self.food.contains(&position) {
+                return Some(first_move);
+            }
+            for direction in DIRECTIONS {
+                if let Some(next) = self.step(position, direction) {
+                    if next != head && !first_moves.contains_key(&next) {
+                        first_moves.insert(next, first_move);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn roomiest(
+        &self,
+        memory: &mut BotMemory,
+        head: Position,
+        heading: Direction,
+    ) -> Option<Direction> {
+        self.safe_directions(head, heading)
+            .into_iter()
+            .max_by_key(|(_, position)| self.room(memory, *position))
+            .map(|(direction, _)| direction)
+    }
+
+    fn greedy(
+        &self,
+        memory: &mut BotMemory,
+        head: Position,
+        heading: Direction,
+    ) -> Option<Direction> {
+        if self.food.is_empty() {
+            return self.roomiest(memory, head, heading);
+        }
+        self.safe_directions(head, heading)
+            .into_iter()
+            .min_by_key(|(_, position)| {
+                self.food
+                    .iter()
+                    .map(|food| self.distance(*position, *food))
+                    .min()
+            })
+            .map(|(direction, _)| direction)
+    }
+
+    pub fn choose(
+        &self,
+        strategy: Strategy,
+        memory: &mut BotMemory,
+        walls: &[Position],
+        head: Position,
+        heading: Direction,
+    ) -> Option<Direction> {
+        match strategy {
+            Strategy::Greedy => self.greedy(memory, head, heading),
+            Strategy::Pathfinder => self
+                .path_to_food(memory, head, heading)
+                .or_else(|| self.roomiest(memory, head, heading)),
+            Strategy::Hamiltonian => memory
+                .cycle(self.arena, walls)
+                .and_then(|cycle| cycle.get(&head).copied())
+                .filter(|direction| {
+                    self.safe_directions(head, heading)
+                        .iter()
+                        .any(|(safe, _)| safe == direction)
+                })
+                .or_else(|| self.choose(Strategy::Pathfinder, memory, walls, head, heading)),
+        }
+    }
+}
+
+/// What the bots keep from one move to the next: the cycle for the round's arena, built once,
+/// and the buffers of the board searches.
+#[derive(Default)]
+pub struct BotMemory {
+    /// The arena and walls the cycle was last built for.
+    cycle_for: Option<(Arena, Vec<Position>)>,
+    cycle: Option<HashMap<Position, Direction>>,
+    seen: HashSet<Position>,
+    first_moves: HashMap<Position, Direction>,
+    queue: VecDeque<Position>,
+}
+
+impl BotMemory {
+    fn cycle(&mut self, arena: Arena, walls: &[Position]) -> Option<&HashMap<Position, Direction>> {
+        let built = Some((arena, walls.to_vec()));
+        if self.cycle_for != built {
+            self.cycle = hamiltonian_cycle(arena, walls);
+            self.cycle_for = built;
+        }
+        self.cycle.as_ref()
+    }
+
+    /// The turns every bot-driven snake takes toward the direction its strategy picks for the
+    /// coming move.
+    pub fn turns(&mut self, simulation: &Simulation) -> Vec<Turn> {
+        let alive = || simulation.snakes.iter().filter(|snake| snake.alive);
+        let board = Board {
+            arena: simulation.arena,
+            blocked: alive()
+                .flat_map(|snake| snake.body.iter())
+                .chain(simulation.walls.iter())
+                .copied()
+                .chain(
+                    simulation
+                        .food
+                        .iter()
+                        .filter(|food| food.kind == FoodKind::Poison)
+                        .map(|food| food.position),
+                )
+                .collect(),
+            heads: alive().map(|snake| snake.head()).collect(),
+            food: simulation
+                .food
+                .iter()
+                .filter(|food| food.kind != FoodKind::Poison)
+                .map(|food| food.position)
+                .collect(),
+        };
+        let mut turns = Vec::new();
+        for (index, snake) in simulation.snakes.iter().enumerate() {
+            if let (true, SnakeController::Bot(strategy)) = (snake.alive, snake.controller) {
+                let heading = snake.heading();
+                if let Some(direction) = board
+                    .choose(strategy, self, &simulation.walls, snake.head(), heading)
+                    .filter(|direction| *direction != heading)
+                {
+                    turns.push(Turn {
+                        snake: index,
+                        steer: Steer::Toward(direction),
+                    });
+                }
+            }
+        }
+        turns
+    }
+}
+
+/// The direction out of every tile along a cycle visiting the whole arena, if the arena is free
+/// of walls and has an even side or wraps around along an odd one.
+pub fn hamiltonian_cycle(arena: Arena, walls: &[Position]) -> Option<HashMap<Position, Direction>> {
+    let (width, height) = (arena.width as i32, arena.height as i32);
+    let (wrap_x, wrap_y) = arena.boundary.wraps();
+    // Rows are laid along the x axis unless transposed; with an odd number of rows the cycle
+    // relies on the rows wrapping around.
+    let transpose = if height % 2 == 0 {
+        false
+    } else if width % 2 == 0 {
+        true
+    } else if wrap_x {
+        false
+    } else if wrap_y {
+        true
+    } else {
+        return None;
+    };
+    let (columns, rows) = if transpose {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    let odd = rows % 2 != 0;
+    if !walls.is_empty() || columns < 2 || (odd && rows > 1 && columns < 3) {
+        return None;
+    }
+    // Snake along the rows leaving column 0 free, then return down column 0. An odd top row
+    // is a ring of its own, entered from and left to the row below it.
+    let top = if odd { rows - 2 } else { rows - 1 };
+    let mut cycle = HashMap::new();
+    for row in 0..rows {
+        for column in 0..columns {
+            let direction = if odd && row == rows - 1 {
+                if rows > 1 && column == columns - 2 {
+                    Direction::Down
+                } else {
+                    Direction::Right
+                }
+            } else if odd && row == rows - 2 && column == columns - 1 {
+                Direction::Up
+            } else if column == 0 {
+                if row == 0 {
+                    Direction::Right
+                } else {
+                    Direction::Down
+                }
+            } else if row % 2 == 0 {
+                if column == columns - 1 {
+                    Direction::Up
+                } else {
+                    Direction::Right
+                }
+            } else if column == 1 {
+                if row == top {
+                    Direction::Left
+                } else {
+                    Direction::Up
+                }
+            } else {
+                Direction::Left
+            };
+            let (position, direction) = if transpose {
+                let direction = match direction {
+                    Direction::Up => Direction::Right,
+                    Direction::Right => Direction::Up,
+                    Direction::Down => Direction::Left,
+                    Direction::Left => Direction::Down,
+                };
+                (Position { x: row, y: column }, direction)
+            } else {
+                (Position { x: column, y: row }, direction)
+            };
+            cycle.insert(position, direction);
+        }
+    }
+    Some(cycle)
+}
+
+/// Steers every bot-driven snake in the direction its strategy picks for the coming move.
+pub fn steer_bots(
+    simulation: Res<Simulation>,
+    mut pending_turns: ResMut<PendingTurns>,
+    mut memory: Local<BotMemory>,
+) {
+    let turns = memory.turns(&simulation);
+    pending_turns.turns.extend(turns);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Boundary, FoodPolicy, GameConfig};
+    use crate::level::Level;
+    use crate::simulation::TickEvent;
+
+    /// Follows the cycle from the origin, checking it visits every tile once and comes back.
+    fn assert_visits_every_tile(arena: Arena) {
+        let cycle = hamiltonian_cycle(arena, &[]).unwrap();
+        let start = Position { x: 0, y: 0 };
+        let mut seen = HashSet::new();
+        let mut position = start;
+        for _ in 0..arena.width * arena.height {
+            assert!(seen.insert(position), "{:?} visited twice", position);
+            position = position.do_move(cycle[&position], arena).unwrap();
+        }
+        assert_eq!(position, start);
+        assert_eq!(seen.len(), (arena.width * arena.height) as usize);
+    }
+
+    #[test]
+    fn hamiltonian_cycle_visits_every_tile_once() {
+        for (width, height) in [(2, 2), (20, 20), (25, 24), (24, 25), (3, 5), (25, 25)] {
+            for boundary in [
+                Boundary::Wrap,
+                Boundary::Walls,
+                Boundary::Mixed {
+                    wrap_x: true,
+                    wrap_y: false,
+                },
+                Boundary::Mixed {
+                    wrap_x: false,
+                    wrap_y: true,
+                },
+            ] {
+                let arena = Arena {
+                    width,
+                    height,
+                    boundary,
+                };
+                if width % 2 != 0 && height % 2 != 0 && boundary == Boundary::Walls {
+                    assert!(hamiltonian_cycle(arena, &[]).is_none());
+                } else {
+                    assert_visits_every_tile(arena);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bots_facing_each_other_do_not_collide() {
+        let level = Level::parse("test", ".......\no>.*.<o\n.......").unwrap();
+        let config = GameConfig {
+            arena: Arena {
+                width: level.width,
+                height: level.height,
+                boundary: Boundary::Walls,
+            },
+            players: 0,
+            bots: vec![Strategy::Greedy, Strategy::Pathfinder],
+            food_policy: FoodPolicy::Timed(1),
+            food_step: 1e9,
+            max_power_ups: 0,
+            speed_ramp: None,
+            ..GameConfig::default()
+        };
+        let mut simulation = Simulation::new(&config, &level, &[], 0);
+        let mut memory = BotMemory::default();
+        for _ in 0..4 {
+            let turns = memory.turns(&simulation);
+            let events = simulation.tick(&turns);
+            assert!(
+                !events
+                    .iter()
+                    .any(|event| matches!(event, TickEvent::Died { .. })),
+                "a bot died on tick {}",
+                simulation.ticks
+            );
+        }
+    }
+}
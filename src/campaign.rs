@@ -0,0 +1,379 @@
+use crate::config::GameConfig;
+use crate::input::{Action, ActionInput};
+use crate::level::Level;
+use crate::simulation::Simulation;
+#[cfg(feature = "render")]
+use crate::{input::Bindings, spawn_overlay, Overlay};
+use crate::{load_ron, save_ron, GameState, RunTime};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const PROGRESS_DIRECTORY: &str = "snake";
+const PROGRESS_FILE: &str = "campaign.ron";
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Goal {
+    EatFood(u32),
+    ReachLength(u32),
+    Survive(f32),
+}
+
+impl Goal {
+    pub fn is_met(&self, eaten: u32, length: u32, elapsed: f32) -> bool {
+        match *self {
+            Goal::EatFood(food) => eaten >= food,
+            Goal::ReachLength(target) => length >= target,
+            Goal::Survive(seconds) => elapsed >= seconds,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match *self {
+            Goal::EatFood(food) => format!("eat {} food", food),
+            Goal::ReachLength(target) => format!("reach length {}", target),
+            Goal::Survive(seconds) => format!("survive {:.0} seconds", seconds),
+        }
+    }
+
+    pub fn progress(&self, eaten: u32, length: u32, elapsed: f32) -> String {
+        match *self {
+            Goal::EatFood(food) => format!("eat {}/{}", eaten.min(food), food),
+            Goal::ReachLength(target) => format!("length {}/{}", length.min(target), target),
+            Goal::Survive(seconds) => format!("survive {:.0}/{:.0}", elapsed.min(seconds), seconds),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CampaignFile {
+    stages: Vec<StageFile>,
+}
+
+#[derive(Deserialize)]
+struct StageFile {
+    level: PathBuf,
+    goal: Goal,
+}
+
+pub struct Stage {
+    pub level: Level,
+    pub goal: Goal,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CampaignProgress {
+    pub unlocked: usize,
+}
+
+impl Default for CampaignProgress {
+    fn default() -> Self {
+        CampaignProgress { unlocked: 1 }
+    }
+}
+
+impl CampaignProgress {
+    pub fn path() -> Option<PathBuf> {
+        dirs::data_dir().map(|data_dir| data_dir.join(PROGRESS_DIRECTORY).join(PROGRESS_FILE))
+    }
+
+    pub fn load() -> CampaignProgress {
+        CampaignProgress::path()
+            .and_then(|path| load_ron(&path, "campaign progress"))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Some(path) = CampaignProgress::path() {
+            save_ron(&path, self, "campaign progress");
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Campaign {
+    pub stages: Vec<Stage>,
+    pub progress: CampaignProgress,
+    pub selected: usize,
+    pub active: Option<usize>,
+    /// Points each player takes into the next stage.
+    pub carried_points: Vec<u32>,
+}
+
+impl Campaign {
+    /// Loads a campaign file listing its stages in order; level paths are relative to it.
+    pub fn load(path: &Path) -> Result<Campaign, String> {
+        let contents = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let campaign_file: CampaignFile =
+            ron::from_str(&contents).map_err(|error| error.to_string())?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        let stages = campaign_file
+            .stages
+            .into_iter()
+            .map(|stage| {
+                let level_path = directory.join(&stage.level);
+                Level::load(&level_path)
+                    .map(|level| Stage {
+                        level,
+                        goal: stage.goal,
+                    })
+                    .map_err(|error| format!("level {:?}: {}", level_path, error))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Campaign {
+            stages,
+            progress: CampaignProgress::load(),
+            ..default()
+        })
+    }
+
+    pub fn unlocked(&self) -> usize {
+        self.progress.unlocked.clamp(1, self.stages.len().max(1))
+    }
+
+    pub fn active_goal(&self) -> Option<Goal> {
+        self.active.map(|index| self.stages[index].goal)
+    }
+
+    /// Records a stage as completed with the points each player takes on, unlocking the stage
+    /// after it. Returns whether that stage was still locked.
+    pub fn complete(&mut self, stage: usize, carried_points: Vec<u32>) -> bool {
+        self.carried_points = carried_points;
+        let unlocked = self.progress.unlocked < stage + 2;
+        if unlocked {
+            self.progress.unlocked = stage + 2;
+        }
+        unlocked
+    }
+}
+
+pub struct FreePlayLevel {
+    pub level: Level,
+    /// Whether the level was generated from the config rather than loaded, so that it follows
+    /// changes of difficulty.
+    pub generated: bool,
+}
+
+fn activate_level(config: &mut GameConfig, current_level: &mut Level, level: &Level) {
+    *current_level = level.clone();
+    config.arena.width = level.width;
+    config.arena.height = level.height;
+}
+
+pub fn start_free_play(
+    config: &mut GameConfig,
+    current_level: &mut Level,
+    free_play_level: &FreePlayLevel,
+    campaign: &mut Campaign,
+) {
+    activate_level(config, current_level, &free_play_level.level);
+    campaign.active = None;
+    campaign.carried_points.clear();
+}
+
+#[cfg(feature = "render")]
+fn level_select_text(campaign: &Campaign, bindings: &Bindings) -> String {
+    let mut text = "CAMPAIGN\n\n".to_string();
+    for (index, stage) in campaign.stages.iter().enumerate() {
+        let marker = if index == campaign.selected { '>' } else { ' ' };
+        if index < campaign.unlocked() {
+            text += &format!(
+                "{} {:>2}  {:<12} {:<22}\n",
+                marker,
+                index + 1,
+                stage.level.name,
+                stage.goal.describe()
+            );
+        } else {
+            text += &format!("{} {:>2}  {:<12} {:<22}\n", marker, index + 1, "locked", "");
+        }
+    }
+    text += &format!(
+        "\n{}/{} to choose, {} to play\npress {} for menu",
+        bindings.describe(Action::TurnUp),
+        bindings.describe(Action::TurnDown),
+        bindings.describe(Action::Confirm),
+        bindings.describe(Action::Back),
+    );
+    text
+}
+
+#[cfg(feature = "render")]
+pub fn spawn_level_select_overlay(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    bindings: Res<Bindings>,
+    mut campaign: ResMut<Campaign>,
+) {
+    campaign.selected = campaign.selected.min(campaign.unlocked() - 1);
+    spawn_overlay(
+        &mut commands,
+        &asset_server,
+        &level_select_text(&campaign, &bindings),
+    );
+}
+
+#[cfg(feature = "render")]
+pub fn handle_level_select_input(
+    mut actions: ResMut<ActionInput>,
+    bindings: Res<Bindings>,
+    mut game_state: ResMut<State<GameState>>,
+    mut config: ResMut<GameConfig>,
+    mut current_level: ResMut<Level>,
+    mut campaign: ResMut<Campaign>,
+    mut overlay_query: Query<&mut Text, With<Overlay>>,
+) {
+    if actions.just_pressed(Action::TurnUp) && campaign.selected > 0 {
+        campaign.selected -= 1;
+    } else if actions.just_pressed(Action::TurnDown) && campaign.selected + 1 < campaign.unlocked()
+    {
+        campaign.selected += 1;
+    } else if actions.just_pressed(Action::Confirm) {
+        actions.consume(Action::Confirm);
+        let selected = campaign.selected;
+        activate_level(
+            &mut config,
+            &mut current_level,
+            &campaign.stages[selected].level,
+        );
+        campaign.active = Some(selected);
+        campaign.carried_points.clear();
+        game_state.set(GameState::Playing).unwrap();
+        return;
+    } else if actions.just_pressed(Action::Back) {
+        actions.consume(Action::Back);
+        game_state.set(GameState::Menu).unwrap();
+        return;
+    } else {
+        return;
+    }
+    for mut text in overlay_query.iter_mut() {
+        text.sections[0].value = level_select_text(&campaign, &bindings);
+    }
+}
+
+pub fn check_goal(
+    mut game_state: ResMut<State<GameState>>,
+    campaign: Res<Campaign>,
+    simulation: Res<Simulation>,
+    run_time: Res<RunTime>,
+) {
+    // A round lost on this very tick stays lost.
+    if simulation.is_over() {
+        return;
+    }
+    if let Some(goal) = campaign.active_goal() {
+        if goal.is_met(
+            simulation.most_eaten(),
+            simulation.longest(),
+            run_time.stopwatch.elapsed_secs(),
+        ) {
+            game_state.overwrite_set(GameState::LevelComplete).unwrap();
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn complete_level(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    bindings: Res<Bindings>,
+    simulation: Res<Simulation>,
+    mut campaign: ResMut<Campaign>,
+) {
+    let completed = campaign.active.unwrap();
+    let points = simulation.snakes.iter().map(|snake| snake.points).collect();
+    if campaign.complete(completed, points) {
+        campaign.progress.save();
+    }
+    let text = if completed + 1 < campaign.stages.len() {
+        format!(
+            "LEVEL {} COMPLETE\n\n{}\n\nnext: {}\n\npress {} to continue\npress {} for menu",
+            completed + 1,
+            simulation.describe_scores(),
+            campaign.stages[completed + 1].goal.describe(),
+            bindings.describe(Action::Confirm),
+            bindings.describe(Action::Back),
+        )
+    } else {
+        format!(
+            "CAMPAIGN COMPLETE\n\n{}\n\npress {} for menu",
+            simulation.describe_scores(),
+            bindings.describe(Action::Confirm),
+        )
+    };
+    spawn_overlay(&mut commands, &asset_server, &text);
+}
+
+pub fn handle_level_complete_input(
+    mut actions: ResMut<ActionInput>,
+    mut game_state: ResMut<State<GameState>>,
+    mut config: ResMut<GameConfig>,
+    mut current_level: ResMut<Level>,
+    mut campaign: ResMut<Campaign>,
+) {
+    let next = campaign.active.unwrap() + 1;
+    if actions.just_pressed(Action::Confirm) {
+        actions.consume(Action::Confirm);
+        if next < campaign.stages.len() {
+            activate_level(
+                &mut config,
+                &mut current_level,
+                &campaign.stages[next].level,
+            );
+            campaign.active = Some(next);
+            campaign.selected = next;
+            game_state.set(GameState::Playing).unwrap();
+        } else {
+            game_state.set(GameState::Menu).unwrap();
+        }
+    } else if actions.just_pressed(Action::Back) {
+        actions.consume(Action::Back);
+        game_state.set(GameState::Menu).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn goals_are_met_once_their_target_is_reached() {
+        let goal = Goal::EatFood(5);
+        assert!(!goal.is_met(4, 100, 100.));
+        assert!(goal.is_met(5, 1, 0.));
+
+        let goal = Goal::ReachLength(8);
+        assert!(!goal.is_met(100, 7, 100.));
+        assert!(goal.is_met(0, 8, 0.));
+
+        let goal = Goal::Survive(30.);
+        assert!(!goal.is_met(100, 100, 29.9));
+        assert!(goal.is_met(0, 1, 30.));
+    }
+
+    #[test]
+    fn completing_a_stage_unlocks_the_next() {
+        let mut campaign = Campaign {
+            stages: (0..3)
+                .map(|_| Stage {
+                    level: Level::parse("stage", ">..").unwrap(),
+                    goal: Goal::EatFood(1),
+                })
+                .collect(),
+            ..default()
+        };
+        assert_eq!(campaign.unlocked(), 1);
+
+        assert!(campaign.complete(0, vec![30]));
+        assert_eq!(campaign.unlocked(), 2);
+        assert_eq!(campaign.carried_points, [30]);
+
+        assert!(!campaign.complete(0, vec![10]));
+        assert_eq!(campaign.unlocked(), 2);
+
+        assert!(campaign.complete(2, vec![50]));
+        assert_eq!(campaign.unlocked(), 3);
+    }
+}
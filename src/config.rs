@@ -0,0 +1,795 @@
+use crate::ai::{hamiltonian_cycle, SnakeController, Strategy};
+use crate::simulation::{FoodKind, PowerUpKind};
+use crate::{Direction, Position};
+use bevy::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CONFIG_DIRECTORY: &str = "snake";
+const CONFIG_FILE: &str = "config.ron";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum Boundary {
+    #[default]
+    Wrap,
+    Walls,
+    Mixed {
+        wrap_x: bool,
+        wrap_y: bool,
+    },
+}
+
+impl Boundary {
+    pub fn wraps(&self) -> (bool, bool) {
+        match *self {
+            Boundary::Wrap => (true, true),
+            Boundary::Walls => (false, false),
+            Boundary::Mixed { wrap_x, wrap_y } => (wrap_x, wrap_y),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ControlScheme {
+    /// Each direction has its own control.
+    Absolute,
+    /// Two controls rotate the heading counter-clockwise and clockwise.
+    Relative,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Arena {
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub boundary: Boundary,
+}
+
+impl Arena {
+    pub fn contains(&self, position: Position) -> bool {
+        position.x >= 0
+            && position.y >= 0
+            && position.x < self.width as i32
+            && position.y < self.height as i32
+    }
+
+    /// Brings a position back into the arena across any edge, walls or not.
+    pub fn wrap(&self, position: Position) -> Position {
+        Position {
+            x: position.x.rem_euclid(self.width as i32),
+            y: position.y.rem_euclid(self.height as i32),
+        }
+    }
+
+    /// Shortest horizontal and vertical steps from one position to another, across the edges
+    /// that wrap.
+    pub fn offset(&self, from: Position, to: Position) -> (i32, i32) {
+        let (wrap_x, wrap_y) = self.boundary.wraps();
+        let axis = |from: i32, to: i32, size: u32, wraps: bool| {
+            let delta = to - from;
+            if wraps && delta.abs() * 2 > size as i32 {
+                delta - delta.signum() * size as i32
+            } else {
+                delta
+            }
+        };
+        (
+            axis(from.x, to.x, self.width, wrap_x),
+            axis(from.y, to.y, self.height, wrap_y),
+        )
+    }
+
+    pub fn confine(&self, position: Position) -> Option<Position> {
+        let (wrap_x, wrap_y) = self.boundary.wraps();
+        let x = if wrap_x {
+            position.x.rem_euclid(self.width as i32)
+        } else {
+            position.x
+        };
+        let y = if wrap_y {
+            position.y.rem_euclid(self.height as i32)
+        } else {
+            position.y
+        };
+        Some(Position { x, y }).filter(|position| self.contains(*position))
+    }
+}
+
+pub const MAX_PLAYERS: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    Insane,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Insane,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+            Difficulty::Insane => "insane",
+        }
+    }
+
+    /// The next or previous preset, wrapping around.
+    pub fn cycle(&self, forward: bool) -> Difficulty {
+        let index = Difficulty::ALL
+            .iter()
+            .position(|difficulty| difficulty == self)
+            .unwrap_or(0);
+        let count = Difficulty::ALL.len();
+        Difficulty::ALL[if forward {
+            (index + 1) % count
+        } else {
+            (index + count - 1) % count
+        }]
+    }
+
+    /// Sets everything the preset bundles: tick rate, speed ramp, arena, obstacles and food.
+    pub fn apply(&self, config: &mut GameConfig) {
+        let (move_step, curve, size, boundary, obstacle_density, food_policy, food_lifetime) =
+            match self {
+                Difficulty::Easy => (
+                    0.12,
+                    vec![(0, 1.), (200, 1.2), (500, 1.4)],
+                    20,
+                    Boundary::Wrap,
+                    0.,
+                    FoodPolicy::Constant(3),
+                    None,
+                ),
+                Difficulty::Normal => (
+                    0.08,
+                    vec![(0, 1.), (100, 1.2), (300, 1.5), (600, 1.8)],
+                    25,
+                    Boundary::Wrap,
+                    0.,
+                    FoodPolicy::Timed(5),
+                    None,
+                ),
+                Difficulty::Hard => (
+                    0.065,
+                    vec![(0, 1.), (100, 1.3), (300, 1.7), (600, 2.1)],
+                    30,
+                    Boundary::Walls,
+                    0.03,
+                    FoodPolicy::Timed(3),
+                    None,
+                ),
+                Difficulty::Insane => (
+                    0.05,
+                    vec![(0, 1.), (100, 1.4), (300, 2.), (600, 2.6)],
+                    35,
+                    Boundary::Walls,
+                    0.06,
+                    FoodPolicy::Constant(1),
+                    Some(8.),
+                ),
+            };
+        config.difficulty = *self;
+        config.move_step = move_step;
+        config.speed_ramp = Some(SpeedRamp {
+            measure: RampMeasure::Score,
+            curve,
+        });
+        config.arena = Arena {
+            width: size,
+            height: size,
+            boundary,
+        };
+        config.obstacle_density = obstacle_density;
+        config.food_policy = food_policy;
+        config.food_lifetime = food_lifetime;
+        let overrides = config.overrides.clone();
+        overrides.apply(config);
+        let center = Position {
+            x: config.arena.width as i32 / 2,
+            y: config.arena.height as i32 / 2,
+        };
+        config.snake_start = overrides.snake_start.unwrap_or_else(|| {
+            (0..3)
+                .map(|offset| Position {
+                    x: center.x + offset,
+                    y: center.y,
+                })
+                .collect()
+        });
+        config.snake_direction = overrides.snake_direction.unwrap_or(Direction::Left);
+    }
+}
+
+/// Preset settings picked by the config file or the command line, which win over any preset.
+#[derive(Clone, Debug, Default)]
+pub struct PresetOverrides {
+    pub arena_width: Option<u32>,
+    pub arena_height: Option<u32>,
+    pub boundary: Option<Boundary>,
+    pub move_step: Option<f64>,
+    pub speed_ramp: Option<Option<SpeedRamp>>,
+    pub obstacle_density: Option<f64>,
+    pub food_policy: Option<FoodPolicy>,
+    pub food_lifetime: Option<Option<f64>>,
+    pub snake_start: Option<Vec<Position>>,
+    pub snake_direction: Option<Direction>,
+}
+
+impl PresetOverrides {
+    /// Applies the overridden settings, except the snake start which depends on the arena.
+    fn apply(&self, config: &mut GameConfig) {
+        if let Some(arena_width) = self.arena_width {
+            config.arena.width = arena_width;
+        }
+        if let Some(arena_height) = self.arena_height {
+            config.arena.height = arena_height;
+        }
+        if let Some(boundary) = self.boundary {
+            config.arena.boundary = boundary;
+        }
+        if let Some(move_step) = self.move_step {
+            config.move_step = move_step;
+        }
+        if let Some(speed_ramp) = &self.speed_ramp {
+            config.speed_ramp = speed_ramp.clone();
+        }
+        if let Some(obstacle_density) = self.obstacle_density {
+            config.obstacle_density = obstacle_density;
+        }
+        if let Some(food_policy) = self.food_policy {
+            config.food_policy = food_policy;
+        }
+        if let Some(food_lifetime) = self.food_lifetime {
+            config.food_lifetime = food_lifetime;
+        }
+    }
+}
+
+/// The preset settings a config file spells out, told apart from the ones it leaves out.
+#[derive(Default, Deserialize)]
+#[serde(rename = "GameConfig", default)]
+struct PresetFields {
+    #[serde(deserialize_with = "present")]
+    arena: Option<ArenaFields>,
+    #[serde(deserialize_with = "present")]
+    move_step: Option<f64>,
+    #[serde(deserialize_with = "present")]
+    speed_ramp: Option<Option<SpeedRamp>>,
+    #[serde(deserialize_with = "present")]
+    obstacle_density: Option<f64>,
+    #[serde(deserialize_with = "present")]
+    food_policy: Option<FoodPolicy>,
+    #[serde(deserialize_with = "present")]
+    food_lifetime: Option<Option<f64>>,
+    #[serde(deserialize_with = "present")]
+    snake_start: Option<Vec<Position>>,
+    #[serde(deserialize_with = "present")]
+    snake_direction: Option<Direction>,
+}
+
+/// An arena as spelled out in a config file, which may leave the boundary to the preset.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename = "Arena")]
+struct ArenaFields {
+    width: u32,
+    height: u32,
+    #[serde(default)]
+    boundary: Option<Boundary>,
+}
+
+impl From<PresetFields> for PresetOverrides {
+    fn from(fields: PresetFields) -> Self {
+        PresetOverrides {
+            arena_width: fields.arena.map(|arena| arena.width),
+            arena_height: fields.arena.map(|arena| arena.height),
+            boundary: fields.arena.and_then(|arena| arena.boundary),
+            move_step: fields.move_step,
+            speed_ramp: fields.speed_ramp,
+            obstacle_density: fields.obstacle_density,
+            food_policy: fields.food_policy,
+            food_lifetime: fields.food_lifetime,
+            snake_start: fields.snake_start,
+            snake_direction: fields.snake_direction,
+        }
+    }
+}
+
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct FoodKindConfig {
+    pub kind: FoodKind,
+    /// Relative chance of new food being of this kind.
+    pub weight: u32,
+    pub color: Color,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct PowerUpConfig {
+    pub kind: PowerUpKind,
+    /// Relative chance of a new power-up being of this kind.
+    pub weight: u32,
+    pub color: Color,
+}
+
+/// What the tick rate speeds up with as a round goes on.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum RampMeasure {
+    Score,
+    Length,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpeedRamp {
+    pub measure: RampMeasure,
+    /// Tick rate multipliers reached at increasing scores or lengths, interpolated in between
+    /// and held after the last.
+    pub curve: Vec<(u32, f64)>,
+}
+
+impl SpeedRamp {
+    pub fn factor(&self, value: u32) -> f64 {
+        let mut previous: Option<(u32, f64)> = None;
+        for &(at, factor) in &self.curve {
+            if value < at {
+                return match previous {
+                    Some((from, from_factor)) => {
+                        from_factor
+                            + (factor - from_factor) * (value - from) as f64 / (at - from) as f64
+                    }
+                    None => factor,
+                };
+            }
+            previous = Some((at, factor));
+        }
+        previous.map_or(1., |(_, factor)| factor)
+    }
+}
+
+/// When new food is dropped on the board.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum FoodPolicy {
+    /// Keeps this many food items on the board, replacing each one as soon as it is eaten.
+    Constant(usize),
+    /// Drops food every `food_step` seconds, at the normal tick rate, while fewer than this many
+    /// items are on the board.
+    Timed(usize),
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct SnakeColors {
+    pub head: Color,
+    pub segment: Color,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    pub window_width: f32,
+    pub window_height: f32,
+    /// Preset the rest of the settings started from, which high scores are kept apart by.
+    pub difficulty: Difficulty,
+    pub arena: Arena,
+    /// Share of the tiles of generated levels taken by obstacles.
+    pub obstacle_density: f64,
+    pub arena_color: Color,
+    /// Seconds between two snake moves before any speed change. The other durations are counted
+    /// in ticks at this rate, so they run shorter while the game is sped up.
+    pub move_step: f64,
+    /// Speeds the game up with the leading player's progress, if set.
+    pub speed_ramp: Option<SpeedRamp>,
+    /// Seconds between two timed food drops, at the normal tick rate.
+    pub food_step: f64,
+    pub food_policy: FoodPolicy,
+    /// Seconds before uneaten food disappears, at the normal tick rate, if it ever does.
+    pub food_lifetime: Option<f64>,
+    /// Kinds of food that can be dropped, with their spawn weights and colors.
+    pub food_kinds: Vec<FoodKindConfig>,
+    pub bonus_points: u32,
+    /// Segments gained from bonus food.
+    pub bonus_growth: u32,
+    /// Tail segments lost to shrinking food.
+    pub shrink_segments: u32,
+    /// Tick rate multipliers of speed-up and slow-down food.
+    pub speed_up_factor: f64,
+    pub slow_down_factor: f64,
+    /// Seconds a speed change from food lasts, at the normal tick rate.
+    pub speed_effect_duration: f64,
+    /// Kinds of power-ups that can be dropped, with their spawn weights and colors.
+    pub power_ups: Vec<PowerUpConfig>,
+    /// Seconds between two power-up spawns, at the normal tick rate.
+    pub power_up_step: f64,
+    pub max_power_ups: usize,
+    /// Seconds a power-up's effect lasts, at the normal tick rate.
+    pub power_up_duration: f64,
+    /// Tiles from a magnetic head within which food is pulled in.
+    pub magnet_range: u32,
+    pub score_multiplier: u32,
+    /// Seed of all gameplay randomness; a fresh one is drawn for every round if unset.
+    pub seed: Option<u64>,
+    pub input_buffer_size: usize,
+    /// Lets paused rounds be stepped back and forth through, a debugging aid; rounds played
+    /// with it on don't enter the high scores.
+    pub debug_rewind: bool,
+    /// Ticks kept to step back through while paused.
+    pub rewind_ticks: usize,
+    pub control_scheme: ControlScheme,
+    pub players: usize,
+    /// Strategies of the bot snakes joining the players on the board.
+    pub bots: Vec<Strategy>,
+    pub food_points: u32,
+    pub snake_head_size: f32,
+    pub snake_segment_size: f32,
+    /// Colors of each player's snake, cycled if there are fewer entries than players.
+    pub snake_colors: Vec<SnakeColors>,
+    pub food_size: f32,
+    pub power_up_size: f32,
+    pub obstacle_size: f32,
+    pub obstacle_color: Color,
+    pub snake_start: Vec<Position>,
+    pub snake_direction: Direction,
+    /// Settings the difficulty presets leave alone.
+    #[serde(skip)]
+    pub overrides: PresetOverrides,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            window_width: 600.,
+            window_height: 600.,
+            difficulty: Difficulty::Normal,
+            arena: Arena {
+                width: 25,
+                height: 25,
+                boundary: Boundary::Wrap,
+            },
+            obstacle_density: 0.,
+            arena_color: Color::rgb(0.08, 0.08, 0.08),
+            move_step: 0.08,
+            speed_ramp: Some(SpeedRamp {
+                measure: RampMeasure::Score,
+                curve: vec![(0, 1.), (100, 1.2), (300, 1.5), (600, 1.8)],
+            }),
+            food_step: 3.0,
+            food_policy: FoodPolicy::Timed(5),
+            food_lifetime: None,
+            food_kinds: vec![
+                FoodKindConfig {
+                    kind: FoodKind::Normal,
+                    weight: 70,
+                    color: Color::rgb(0.2, 0.8, 0.2),
+                },
+                FoodKindConfig {
+                    kind: FoodKind::Bonus,
+                    weight: 10,
+                    color: Color::rgb(1.0, 0.85, 0.1),
+                },
+                FoodKindConfig {
+                    kind: FoodKind::Shrink,
+                    weight: 5,
+                    color: Color::rgb(0.3, 0.85, 0.9),
+                },
+                FoodKindConfig {
+                    kind: FoodKind::SpeedUp,
+                    weight: 5,
+                    color: Color::rgb(1.0, 0.35, 0.1),
+                },
+                FoodKindConfig {
+                    kind: FoodKind::SlowDown,
+                    weight: 5,
+                    color: Color::rgb(0.65, 0.8, 1.0),
+                },
+                FoodKindConfig {
+                    kind: FoodKind::Poison,
+                    weight: 5,
+                    color: Color::rgb(0.55, 0.0, 0.45),
+                },
+            ],
+            bonus_points: 50,
+            bonus_growth: 3,
+            shrink_segments: 3,
+            speed_up_factor: 1.5,
+            slow_down_factor: 0.6,
+            speed_effect_duration: 5.,
+            power_ups: vec![
+                PowerUpConfig {
+                    kind: PowerUpKind::Ghost,
+                    weight: 1,
+                    color: Color::rgba(0.9, 0.9, 1.0, 0.6),
+                },
+                PowerUpConfig {
+                    kind: PowerUpKind::Invincible,
+                    weight: 1,
+                    color: Color::rgb(1.0, 1.0, 0.6),
+                },
+                PowerUpConfig {
+                    kind: PowerUpKind::Magnet,
+                    weight: 1,
+                    color: Color::rgb(0.9, 0.2, 0.3),
+                },
+                PowerUpConfig {
+                    kind: PowerUpKind::Multiplier,
+                    weight: 1,
+                    color: Color::rgb(0.3, 1.0, 0.6),
+                },
+                PowerUpConfig {
+                    kind: PowerUpKind::Reverse,
+                    weight: 1,
+                    color: Color::rgb(0.5, 0.3, 0.1),
+                },
+            ],
+            power_up_step: 12.,
+            max_power_ups: 1,
+            power_up_duration: 8.,
+            magnet_range: 4,
+            score_multiplier: 2,
+            seed: None,
+            input_buffer_size: 3,
+            debug_rewind: false,
+            rewind_ticks: 300,
+            control_scheme: ControlScheme::Absolute,
+            players: 1,
+            bots: Vec::new(),
+            food_points: 10,
+            snake_head_size: 0.8,
+            snake_segment_size: 0.5,
+            snake_colors: vec![
+                SnakeColors {
+                    head: Color::rgb(0.8, 0.8, 0.8),
+                    segment: Color::rgb(0.6, 0.6, 0.6),
+                },
+                SnakeColors {
+                    head: Color::rgb(0.4, 0.6, 1.0),
+                    segment: Color::rgb(0.25, 0.4, 0.8),
+                },
+                SnakeColors {
+                    head: Color::rgb(1.0, 0.6, 0.2),
+                    segment: Color::rgb(0.8, 0.45, 0.1),
+                },
+                SnakeColors {
+                    head: Color::rgb(0.8, 0.4, 0.9),
+                    segment: Color::rgb(0.6, 0.25, 0.7),
+                },
+            ],
+            food_size: 0.6,
+            power_up_size: 0.55,
+            obstacle_size: 1.0,
+            obstacle_color: Color::rgb(0.35, 0.25, 0.2),
+            snake_start: vec![
+                Position { x: 12, y: 12 },
+                Position { x: 13, y: 12 },
+                Position { x: 14, y: 12 },
+            ],
+            snake_direction: Direction::Left,
+            overrides: PresetOverrides::default(),
+        }
+    }
+}
+
+impl GameConfig {
+    /// Number of snakes on the board: the players' followed by the bots'.
+    pub fn snakes(&self) -> usize {
+        self.players + self.bots.len()
+    }
+
+    pub fn controller(&self, snake: usize) -> SnakeController {
+        match snake.checked_sub(self.players) {
+            Some(bot) => SnakeController::Bot(self.bots[bot]),
+            None => SnakeController::Player(snake),
+        }
+    }
+
+    pub fn snake_colors(&self, player: usize) -> SnakeColors {
+        self.snake_colors[player % self.snake_colors.len()]
+    }
+
+    pub fn food_color(&self, kind: FoodKind) -> Color {
+        self.food_kinds
+            .iter()
+            .find(|food_kind| food_kind.kind == kind)
+            .map_or(Color::GRAY, |food_kind| food_kind.color)
+    }
+
+    pub fn power_up_color(&self, kind: PowerUpKind) -> Color {
+        self.power_ups
+            .iter()
+            .find(|power_up| power_up.kind == kind)
+            .map_or(Color::GRAY, |power_up| power_up.color)
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|config_dir| config_dir.join(CONFIG_DIRECTORY).join(CONFIG_FILE))
+    }
+
+    pub fn load(path: &Path) -> Result<GameConfig, String> {
+        let contents = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        GameConfig::parse(&contents)
+    }
+
+    /// Reads a config, applying its difficulty preset under the settings it spells out.
+    pub fn parse(contents: &str) -> Result<GameConfig, String> {
+        let mut config: GameConfig = ron::from_str(contents).map_err(|error| error.to_string())?;
+        let fields: PresetFields = ron::from_str(contents).map_err(|error| error.to_string())?;
+        config.overrides = fields.into();
+        let difficulty = config.difficulty;
+        difficulty.apply(&mut config);
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.validate_rules()?;
+        if self.snake_start.is_empty() {
+            return Err("the starting snake needs at least a head".to_string());
+        }
+        if !self
+            .snake_start
+            .iter()
+            .all(|position| self.arena.contains(*position))
+        {
+            return Err("the starting snake must lie inside the arena".to_string());
+        }
+        Ok(())
+    }
+
+    /// Checks everything but the starting snake, which only generated levels use.
+    pub fn validate_rules(&self) -> Result<(), String> {
+        if self.arena.width == 0 || self.arena.height == 0 {
+            return Err("arena width and height must be positive".to_string());
+        }
+        if self.move_step <= 0. || self.food_step <= 0. {
+            return Err("move and food steps must be positive".to_string());
+        }
+        if let FoodPolicy::Constant(0) | FoodPolicy::Timed(0) = self.food_policy {
+            return Err("the food policy must allow at least one food item".to_string());
+        }
+        if self
+            .food_kinds
+            .iter()
+            .all(|food_kind| food_kind.weight == 0)
+        {
+            return Err("at least one food kind must have a positive weight".to_string());
+        }
+        if let Some(speed_ramp) = &self.speed_ramp {
+            if speed_ramp.curve.iter().any(|(_, factor)| *factor <= 0.) {
+                return Err("speed ramp factors must be positive".to_string());
+            }
+            if speed_ramp
+                .curve
+                .windows(2)
+                .any(|pair| pair[0].0 >= pair[1].0)
+            {
+                return Err("speed ramp scores or lengths must increase".to_string());
+            }
+        }
+        if !(0. ..=0.2).contains(&self.obstacle_density) {
+            return Err("obstacle density must be between 0 and 0.2".to_string());
+        }
+        if self.power_up_step <= 0. || self.power_up_duration <= 0. {
+            return Err("power-up step and duration must be positive".to_string());
+        }
+        if self.speed_up_factor <= 0. || self.slow_down_factor <= 0. {
+            return Err("speed factors must be positive".to_string());
+        }
+        if matches!(self.food_lifetime, Some(lifetime) if lifetime <= 0.) {
+            return Err("food lifetime must be positive".to_string());
+        }
+        if self.input_buffer_size == 0 {
+            return Err("the input buffer must hold at least one turn".to_string());
+        }
+        if self.players > MAX_PLAYERS {
+            return Err(format!("there can be at most {} players", MAX_PLAYERS));
+        }
+        if !(1..=MAX_PLAYERS).contains(&self.snakes()) {
+            return Err(format!(
+                "there must be between 1 and {} snakes, bots included",
+                MAX_PLAYERS
+            ));
+        }
+        if self.bots.contains(&Strategy::Hamiltonian)
+            && hamiltonian_cycle(self.arena, &[]).is_none()
+        {
+            return Err(
+                "hamiltonian bots need an arena with an even side or wrapping around an odd one"
+                    .to_string(),
+            );
+        }
+        if self.snake_colors.is_empty() {
+            return Err("at least one snake color is needed".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_file_settings_survive_presets() {
+        let mut config =
+            GameConfig::parse("(arena: (width: 40, height: 22), food_policy: Constant(2))")
+                .unwrap();
+        Difficulty::Insane.apply(&mut config);
+        assert_eq!((config.arena.width, config.arena.height), (40, 22));
+        assert_eq!(config.arena.boundary, Boundary::Walls);
+        assert_eq!(config.food_policy, FoodPolicy::Constant(2));
+        assert_eq!(config.move_step, 0.05);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn command_line_overrides_survive_cycling_presets() {
+        let mut config = GameConfig::default();
+        config.overrides.arena_width = Some(40);
+        let mut difficulty = config.difficulty;
+        for _ in Difficulty::ALL {
+            difficulty = difficulty.cycle(true);
+            difficulty.apply(&mut config);
+            assert_eq!(config.difficulty, difficulty);
+            assert_eq!(config.arena.width, 40);
+            assert!(config.validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn disabled_speed_ramp_stays_disabled() {
+        let mut config = GameConfig::parse("(speed_ramp: None)").unwrap();
+        assert!(config.speed_ramp.is_none());
+        for difficulty in Difficulty::ALL {
+            difficulty.apply(&mut config);
+            assert!(config.speed_ramp.is_none());
+        }
+    }
+
+    #[test]
+    fn speed_ramps_interpolate_and_hold_after_the_last_point() {
+        for difficulty in Difficulty::ALL {
+            let mut config = GameConfig::default();
+            difficulty.apply(&mut config);
+            let ramp = config.speed_ramp.unwrap();
+            assert_eq!(ramp.factor(0), 1.);
+            for pair in ramp.curve.windows(2) {
+                let ((from, from_factor), (to, to_factor)) = (pair[0], pair[1]);
+                assert_eq!(ramp.factor(from), from_factor);
+                let middle = ramp.factor((from + to) / 2);
+                assert!((middle - (from_factor + to_factor) / 2.).abs() < 1e-9);
+            }
+            let &(last, max) = ramp.curve.last().unwrap();
+            assert_eq!(ramp.factor(last), max);
+            assert_eq!(ramp.factor(u32::MAX), max);
+        }
+    }
+
+    #[test]
+    fn speed_ramp_holds_its_first_factor_before_the_first_point() {
+        let ramp = SpeedRamp {
+            measure: RampMeasure::Length,
+            curve: vec![(10, 1.5), (20, 2.)],
+        };
+        assert_eq!(ramp.factor(0), 1.5);
+        assert_eq!(ramp.factor(15), 1.75);
+        let flat = SpeedRamp {
+            measure: RampMeasure::Score,
+            curve: Vec::new(),
+        };
+        assert_eq!(flat.factor(100), 1.);
+    }
+}
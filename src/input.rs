@@ -0,0 +1,730 @@
+use crate::config::MAX_PLAYERS;
+use crate::{load_ron, save_ron};
+#[cfg(feature = "render")]
+use crate::{spawn_overlay, GameState, Overlay};
+use bevy::input::gamepad::{
+    Gamepad, GamepadAxis, GamepadAxisType, GamepadButton, GamepadButtonType, Gamepads,
+};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+const BINDINGS_DIRECTORY: &str = "snake";
+const BINDINGS_FILE: &str = "controls.ron";
+const AXIS_THRESHOLD: f32 = 0.5;
+#[cfg(feature = "render")]
+const STICK_AXES: [GamepadAxisType; 4] = [
+    GamepadAxisType::LeftStickX,
+    GamepadAxisType::LeftStickY,
+    GamepadAxisType::RightStickX,
+    GamepadAxisType::RightStickY,
+];
+/// Default up, right, down and left keys of each player.
+const PLAYER_KEYS: [[KeyCode; 4]; MAX_PLAYERS] = [
+    [KeyCode::W, KeyCode::D, KeyCode::S, KeyCode::A],
+    [KeyCode::Up, KeyCode::Right, KeyCode::Down, KeyCode::Left],
+    [KeyCode::I, KeyCode::L, KeyCode::K, KeyCode::J],
+    [
+        KeyCode::Numpad8,
+        KeyCode::Numpad6,
+        KeyCode::Numpad5,
+        KeyCode::Numpad4,
+    ],
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Action {
+    TurnUp,
+    TurnRight,
+    TurnDown,
+    TurnLeft,
+    TurnCounterClockwise,
+    TurnClockwise,
+    Pause,
+    Restart,
+    Confirm,
+    Back,
+    Campaign,
+    Controls,
+    Replay,
+    StepBack,
+    StepForward,
+}
+
+impl Action {
+    pub const ALL: [Action; 15] = [
+        Action::TurnUp,
+        Action::TurnRight,
+        Action::TurnDown,
+        Action::TurnLeft,
+        Action::TurnCounterClockwise,
+        Action::TurnClockwise,
+        Action::Pause,
+        Action::Restart,
+        Action::Confirm,
+        Action::Back,
+        Action::Campaign,
+        Action::Controls,
+        Action::Replay,
+        Action::StepBack,
+        Action::StepForward,
+    ];
+
+    /// Actions the menus cannot be left or used without, which always keep a shared binding.
+    pub const ESSENTIAL: [Action; 3] = [Action::Confirm, Action::Back, Action::Pause];
+
+    /// Actions that each player binds separately in multiplayer.
+    pub const PLAYER: [Action; 6] = [
+        Action::TurnUp,
+        Action::TurnRight,
+        Action::TurnDown,
+        Action::TurnLeft,
+        Action::TurnCounterClockwise,
+        Action::TurnClockwise,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::TurnUp => "turn up",
+            Action::TurnRight => "turn right",
+            Action::TurnDown => "turn down",
+            Action::TurnLeft => "turn left",
+            Action::TurnCounterClockwise => "rotate ccw",
+            Action::TurnClockwise => "rotate cw",
+            Action::Pause => "pause",
+            Action::Restart => "restart",
+            Action::Confirm => "confirm",
+            Action::Back => "back",
+            Action::Campaign => "campaign",
+            Action::Controls => "controls",
+            Action::Replay => "replay",
+            Action::StepBack => "step back",
+            Action::StepForward => "step forward",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    GamepadButton(GamepadButtonType),
+    GamepadAxis {
+        axis: GamepadAxisType,
+        positive: bool,
+    },
+}
+
+impl Binding {
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key_code) => format!("{:?}", key_code).to_lowercase(),
+            Binding::GamepadButton(button_type) => format!("{:?}", button_type),
+            Binding::GamepadAxis { axis, positive } => {
+                format!("{:?}{}", axis, if *positive { '+' } else { '-' })
+            }
+        }
+    }
+
+    fn is_pressed(
+        &self,
+        keyboard_input: &Input<KeyCode>,
+        gamepads: &[Gamepad],
+        gamepad_buttons: &Input<GamepadButton>,
+        gamepad_axes: &Axis<GamepadAxis>,
+    ) -> bool {
+        match *self {
+            Binding::Key(key_code) => keyboard_input.pressed(key_code),
+            Binding::GamepadButton(button_type) => gamepads
+                .iter()
+                .any(|gamepad| gamepad_buttons.pressed(GamepadButton(*gamepad, button_type))),
+            Binding::GamepadAxis { axis, positive } => gamepads.iter().any(|gamepad| {
+                gamepad_axes
+                    .get(GamepadAxis(*gamepad, axis))
+                    .is_some_and(|value| {
+                        if positive {
+                            value > AXIS_THRESHOLD
+                        } else {
+                            value < -AXIS_THRESHOLD
+                        }
+                    })
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Bindings {
+    pub actions: HashMap<Action, Vec<Binding>>,
+    /// Per-player steering used in multiplayer; player N also owns the Nth gamepad.
+    #[serde(default)]
+    pub players: Vec<HashMap<Action, Vec<Binding>>>,
+}
+
+fn default_player_actions(player: usize) -> HashMap<Action, Vec<Binding>> {
+    let [up, right, down, left] = PLAYER_KEYS[player];
+    let turn = |key_code, dpad, axis, positive| {
+        vec![
+            Binding::Key(key_code),
+            Binding::GamepadButton(dpad),
+            Binding::GamepadAxis { axis, positive },
+        ]
+    };
+    let mut actions = HashMap::default();
+    actions.insert(
+        Action::TurnUp,
+        turn(
+            up,
+            GamepadButtonType::DPadUp,
+            GamepadAxisType::LeftStickY,
+            true,
+        ),
+    );
+    actions.insert(
+        Action::TurnRight,
+        turn(
+            right,
+            GamepadButtonType::DPadRight,
+            GamepadAxisType::LeftStickX,
+            true,
+        ),
+    );
+    actions.insert(
+        Action::TurnDown,
+        turn(
+            down,
+            GamepadButtonType::DPadDown,
+            GamepadAxisType::LeftStickY,
+            false,
+        ),
+    );
+    actions.insert(
+        Action::TurnLeft,
+        turn(
+            left,
+            GamepadButtonType::DPadLeft,
+            GamepadAxisType::LeftStickX,
+            false,
+        ),
+    );
+    actions.insert(
+        Action::TurnCounterClockwise,
+        turn(
+            left,
+            GamepadButtonType::LeftTrigger,
+            GamepadAxisType::LeftStickX,
+            false,
+        ),
+    );
+    actions.insert(
+        Action::TurnClockwise,
+        turn(
+            right,
+            GamepadButtonType::RightTrigger,
+            GamepadAxisType::LeftStickX,
+            true,
+        ),
+    );
+    actions
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let turn = |key_code, alternative_key_code, dpad, axis, positive| {
+            vec![
+                Binding::Key(key_code),
+                Binding::Key(alternative_key_code),
+                Binding::GamepadButton(dpad),
+                Binding::GamepadAxis { axis, positive },
+            ]
+        };
+        let mut actions = HashMap::default();
+        actions.insert(
+            Action::TurnUp,
+            turn(
+                KeyCode::Up,
+                KeyCode::W,
+                GamepadButtonType::DPadUp,
+                GamepadAxisType::LeftStickY,
+                true,
+            ),
+        );
+        actions.insert(
+            Action::TurnRight,
+            turn(
+                KeyCode::Right,
+                KeyCode::D,
+                GamepadButtonType::DPadRight,
+                GamepadAxisType::LeftStickX,
+                true,
+            ),
+        );
+        actions.insert(
+            Action::TurnDown,
+            turn(
+                KeyCode::Down,
+                KeyCode::S,
+                GamepadButtonType::DPadDown,
+                GamepadAxisType::LeftStickY,
+                false,
+            ),
+        );
+        actions.insert(
+            Action::TurnLeft,
+            turn(
+                KeyCode::Left,
+                KeyCode::A,
+                GamepadButtonType::DPadLeft,
+                GamepadAxisType::LeftStickX,
+                false,
+            ),
+        );
+        actions.insert(
+            Action::TurnCounterClockwise,
+            turn(
+                KeyCode::Left,
+                KeyCode::A,
+                GamepadButtonType::LeftTrigger,
+                GamepadAxisType::LeftStickX,
+                false,
+            ),
+        );
+        actions.insert(
+            Action::TurnClockwise,
+            turn(
+                KeyCode::Right,
+                KeyCode::D,
+                GamepadButtonType::RightTrigger,
+                GamepadAxisType::LeftStickX,
+                true,
+            ),
+        );
+        actions.insert(
+            Action::Pause,
+            vec![
+                Binding::Key(KeyCode::Escape),
+                Binding::Key(KeyCode::P),
+                Binding::GamepadButton(GamepadButtonType::Start),
+            ],
+        );
+        actions.insert(
+            Action::Restart,
+            vec![
+                Binding::Key(KeyCode::Space),
+                Binding::Key(KeyCode::R),
+                Binding::GamepadButton(GamepadButtonType::South),
+            ],
+        );
+        actions.insert(
+            Action::Confirm,
+            vec![
+                Binding::Key(KeyCode::Space),
+                Binding::Key(KeyCode::Return),
+                Binding::GamepadButton(GamepadButtonType::South),
+            ],
+        );
+        actions.insert(
+            Action::Back,
+            vec![
+                Binding::Key(KeyCode::Escape),
+                Binding::GamepadButton(GamepadButtonType::East),
+            ],
+        );
+        actions.insert(
+            Action::Campaign,
+            vec![
+                Binding::Key(KeyCode::C),
+                Binding::GamepadButton(GamepadButtonType::North),
+            ],
+        );
+        actions.insert(
+            Action::Controls,
+            vec![
+                Binding::Key(KeyCode::K),
+                Binding::GamepadButton(GamepadButtonType::West),
+            ],
+        );
+        actions.insert(
+            Action::Replay,
+            vec![
+                Binding::Key(KeyCode::V),
+                Binding::GamepadButton(GamepadButtonType::Select),
+            ],
+        );
+        actions.insert(
+            Action::StepBack,
+            vec![
+                Binding::Key(KeyCode::Comma),
+                Binding::GamepadButton(GamepadButtonType::LeftTrigger2),
+            ],
+        );
+        actions.insert(
+            Action::StepForward,
+            vec![
+                Binding::Key(KeyCode::Period),
+                Binding::GamepadButton(GamepadButtonType::RightTrigger2),
+            ],
+        );
+        Bindings {
+            actions,
+            players: (0..MAX_PLAYERS).map(default_player_actions).collect(),
+        }
+    }
+}
+
+impl Bindings {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|config_dir| config_dir.join(BINDINGS_DIRECTORY).join(BINDINGS_FILE))
+    }
+
+    pub fn load() -> Bindings {
+        Bindings::path()
+            .and_then(|path| load_ron(&path, "controls"))
+            .map_or_else(Bindings::default, Bindings::with_defaults)
+    }
+
+    /// Fills in the default bindings of actions a saved file lacks, and of essential actions
+    /// it left unbound, so that the menus can always be worked.
+    fn with_defaults(mut self) -> Bindings {
+        let defaults = Bindings::default();
+        for (action, action_defaults) in defaults.actions {
+            match self.actions.get(&action) {
+                Some(action_bindings)
+                    if !action_bindings.is_empty() || !Action::ESSENTIAL.contains(&action) => {}
+                _ => {
+                    self.actions.insert(action, action_defaults);
+                }
+            }
+        }
+        self.players.resize_with(MAX_PLAYERS, HashMap::default);
+        for (player_actions, player_defaults) in self.players.iter_mut().zip(defaults.players) {
+            for (action, action_defaults) in player_defaults {
+                player_actions.entry(action).or_insert(action_defaults);
+            }
+        }
+        self
+    }
+
+    pub fn save(&self) {
+        if let Some(path) = Bindings::path() {
+            save_ron(&path, self, "controls");
+        }
+    }
+
+    /// The shared bindings, or those of one player.
+    pub fn actions(&self, player: Option<usize>) -> &HashMap<Action, Vec<Binding>> {
+        match player {
+            Some(player) => &self.players[player],
+            None => &self.actions,
+        }
+    }
+
+    pub fn actions_mut(&mut self, player: Option<usize>) -> &mut HashMap<Action, Vec<Binding>> {
+        match player {
+            Some(player) => &mut self.players[player],
+            None => &mut self.actions,
+        }
+    }
+
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.get_for(None, action)
+    }
+
+    pub fn get_for(&self, player: Option<usize>, action: Action) -> &[Binding] {
+        self.actions(player)
+            .get(&action)
+            .map(|bindings| bindings.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn bind(&mut self, player: Option<usize>, action: Action, binding: Binding) {
+        let bindings = self.actions_mut(player).entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    /// Unbinds an action, unless it is essential to the menus.
+    pub fn clear(&mut self, player: Option<usize>, action: Action) {
+        if player.is_none() && Action::ESSENTIAL.contains(&action) {
+            return;
+        }
+        self.actions_mut(player).insert(action, Vec::new());
+    }
+
+    /// Names the first binding of an action, for prompts like "press space to start".
+    pub fn describe(&self, action: Action) -> String {
+        self.get(action)
+            .first()
+            .map_or("<unbound>".to_string(), Binding::name)
+    }
+}
+
+#[derive(Default)]
+pub struct ActionInput {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    player_pressed: Vec<HashSet<Action>>,
+    player_just_pressed: Vec<HashSet<Action>>,
+}
+
+impl ActionInput {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn player_just_pressed(&self, player: usize, action: Action) -> bool {
+        self.player_just_pressed
+            .get(player)
+            .is_some_and(|just_pressed| just_pressed.contains(&action))
+    }
+
+    /// Marks a press as handled so that systems running later in the same frame (possibly
+    /// in a freshly entered state) do not react to it again.
+    pub fn consume(&mut self, action: Action) {
+        self.just_pressed.remove(&action);
+    }
+}
+
+fn pressed_actions(
+    actions: &HashMap<Action, Vec<Binding>>,
+    keyboard_input: &Input<KeyCode>,
+    gamepads: &[Gamepad],
+    gamepad_buttons: &Input<GamepadButton>,
+    gamepad_axes: &Axis<GamepadAxis>,
+) -> HashSet<Action> {
+    actions
+        .iter()
+        .filter(|(_, bindings)| {
+            bindings.iter().any(|binding| {
+                binding.is_pressed(keyboard_input, gamepads, gamepad_buttons, gamepad_axes)
+            })
+        })
+        .map(|(action, _)| *action)
+        .collect()
+}
+
+pub fn update_actions(
+    bindings: Res<Bindings>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    mut actions: ResMut<ActionInput>,
+) {
+    let mut gamepads: Vec<Gamepad> = gamepads.iter().copied().collect();
+    gamepads.sort_by_key(|gamepad| gamepad.0);
+
+    let pressed = pressed_actions(
+        &bindings.actions,
+        &keyboard_input,
+        &gamepads,
+        &gamepad_buttons,
+        &gamepad_axes,
+    );
+    actions.just_pressed = pressed.difference(&actions.pressed).copied().collect();
+    actions.pressed = pressed;
+
+    let player_pressed: Vec<HashSet<Action>> = bindings
+        .players
+        .iter()
+        .enumerate()
+        .map(|(player, player_actions)| {
+            let gamepad = gamepads.get(player).map(std::slice::from_ref);
+            pressed_actions(
+                player_actions,
+                &keyboard_input,
+                gamepad.unwrap_or_default(),
+                &gamepad_buttons,
+                &gamepad_axes,
+            )
+        })
+        .collect();
+    actions.player_just_pressed = player_pressed
+        .iter()
+        .enumerate()
+        .map(
+            |(player, pressed)| match actions.player_pressed.get(player) {
+                Some(previous) => pressed.difference(previous).copied().collect(),
+                None => pressed.clone(),
+            },
+        )
+        .collect();
+    actions.player_pressed = player_pressed;
+}
+
+#[derive(Default)]
+pub struct Rebinding {
+    /// Whose bindings are being edited: the shared ones or a single player's.
+    pub player: Option<usize>,
+    pub selected: usize,
+    pub capturing: bool,
+}
+
+impl Rebinding {
+    pub fn actions(&self) -> &'static [Action] {
+        match self.player {
+            Some(_) => &Action::PLAYER,
+            None => &Action::ALL,
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+fn captured_binding(
+    keyboard_input: &Input<KeyCode>,
+    gamepads: &Gamepads,
+    gamepad_buttons: &Input<GamepadButton>,
+    gamepad_axes: &Axis<GamepadAxis>,
+) -> Option<Binding> {
+    if let Some(key_code) = keyboard_input.get_just_pressed().next() {
+        return Some(Binding::Key(*key_code));
+    }
+    if let Some(GamepadButton(_, button_type)) = gamepad_buttons.get_just_pressed().next() {
+        return Some(Binding::GamepadButton(*button_type));
+    }
+    gamepads.iter().find_map(|gamepad: &Gamepad| {
+        STICK_AXES.iter().find_map(|axis| {
+            gamepad_axes
+                .get(GamepadAxis(*gamepad, *axis))
+                .filter(|value| value.abs() > AXIS_THRESHOLD)
+                .map(|value| Binding::GamepadAxis {
+                    axis: *axis,
+                    positive: value > 0.,
+                })
+        })
+    })
+}
+
+#[cfg(feature = "render")]
+fn controls_text(bindings: &Bindings, rebinding: &Rebinding) -> String {
+    let mut text = match rebinding.player {
+        Some(player) => format!("CONTROLS: PLAYER {}\n\n", player + 1),
+        None => "CONTROLS: SHARED\n\n".to_string(),
+    };
+    for (index, action) in rebinding.actions().iter().enumerate() {
+        let marker = if index == rebinding.selected {
+            '>'
+        } else {
+            ' '
+        };
+        let names = if index == rebinding.selected && rebinding.capturing {
+            "press a key or button...".to_string()
+        } else {
+            bindings
+                .get_for(rebinding.player, *action)
+                .iter()
+                .take(3)
+                .map(Binding::name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        text += &format!("{} {:<11} {:<36}\n", marker, action.name(), names);
+    }
+    text += &format!(
+        "\n{}/{} to switch player\n{} to add a binding, backspace to clear\n{} to save and go back",
+        bindings.describe(Action::TurnLeft),
+        bindings.describe(Action::TurnRight),
+        bindings.describe(Action::Confirm),
+        bindings.describe(Action::Back),
+    );
+    text
+}
+
+#[cfg(feature = "render")]
+pub fn spawn_controls_overlay(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    bindings: Res<Bindings>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    *rebinding = Rebinding::default();
+    spawn_overlay(
+        &mut commands,
+        &asset_server,
+        &controls_text(&bindings, &rebinding),
+    );
+}
+
+#[cfg(feature = "render")]
+#[allow(clippy::too_many_arguments)]
+pub fn handle_controls_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    mut actions: ResMut<ActionInput>,
+    mut bindings: ResMut<Bindings>,
+    mut rebinding: ResMut<Rebinding>,
+    mut game_state: ResMut<State<GameState>>,
+    mut overlay_query: Query<&mut Text, With<Overlay>>,
+) {
+    let action = rebinding.actions()[rebinding.selected];
+    // Pages cycle through the shared bindings followed by each player's.
+    let page = rebinding.player.map_or(0, |player| player + 1);
+    if rebinding.capturing {
+        match captured_binding(&keyboard_input, &gamepads, &gamepad_buttons, &gamepad_axes) {
+            Some(binding) => {
+                bindings.bind(rebinding.player, action, binding);
+                rebinding.capturing = false;
+            }
+            None => return,
+        }
+    } else if actions.just_pressed(Action::TurnUp) && rebinding.selected > 0 {
+        rebinding.selected -= 1;
+    } else if actions.just_pressed(Action::TurnDown)
+        && rebinding.selected + 1 < rebinding.actions().len()
+    {
+        rebinding.selected += 1;
+    } else if actions.just_pressed(Action::TurnLeft) || actions.just_pressed(Action::TurnRight) {
+        let page = if actions.just_pressed(Action::TurnLeft) {
+            (page + MAX_PLAYERS) % (MAX_PLAYERS + 1)
+        } else {
+            (page + 1) % (MAX_PLAYERS + 1)
+        };
+        rebinding.player = page.checked_sub(1);
+        rebinding.selected = rebinding.selected.min(rebinding.actions().len() - 1);
+    } else if actions.just_pressed(Action::Confirm) {
+        actions.consume(Action::Confirm);
+        rebinding.capturing = true;
+    } else if keyboard_input.just_pressed(KeyCode::Back) {
+        bindings.clear(rebinding.player, action);
+    } else if actions.just_pressed(Action::Back) {
+        actions.consume(Action::Back);
+        bindings.save();
+        game_state.set(GameState::Menu).unwrap();
+        return;
+    } else {
+        return;
+    }
+    for mut text in overlay_query.iter_mut() {
+        text.sections[0].value = controls_text(&bindings, &rebinding);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_essential_bindings_get_their_defaults_back() {
+        let saved: Bindings =
+            ron::from_str("(actions: {Back: [Key(Q)], Pause: [], Replay: []}, players: [])")
+                .unwrap();
+        let bindings = saved.with_defaults();
+        let defaults = Bindings::default();
+        assert_eq!(bindings.get(Action::Pause), defaults.get(Action::Pause));
+        assert_eq!(bindings.get(Action::Confirm), defaults.get(Action::Confirm));
+        assert_eq!(bindings.get(Action::Back), [Binding::Key(KeyCode::Q)]);
+        assert!(bindings.get(Action::Replay).is_empty());
+        assert_eq!(bindings.players.len(), MAX_PLAYERS);
+        assert_eq!(
+            bindings.get_for(Some(0), Action::TurnUp),
+            defaults.get_for(Some(0), Action::TurnUp)
+        );
+    }
+}
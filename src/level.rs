@@ -0,0 +1,293 @@
+use crate::config::GameConfig;
+use crate::{Direction, Position};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+const DEFAULT_LEVEL_NAME: &str = "open";
+const GENERATED_SNAKE_LENGTH: i32 = 3;
+/// Fixed so that every arena size and density always gets the same obstacles, keeping high
+/// scores comparable.
+const OBSTACLE_SEED: u64 = 0x5eed;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SnakeStart {
+    /// Segment positions, head first.
+    pub body: Vec<Position>,
+    pub direction: Direction,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Level {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub walls: Vec<Position>,
+    pub snakes: Vec<SnakeStart>,
+    pub food: Vec<Position>,
+}
+
+impl Level {
+    /// An arena of the configured size, with obstacles scattered as densely as configured.
+    pub fn open(config: &GameConfig) -> Level {
+        let mut level = Level {
+            name: DEFAULT_LEVEL_NAME.to_string(),
+            width: config.arena.width,
+            height: config.arena.height,
+            walls: Vec::new(),
+            snakes: vec![SnakeStart {
+                body: config.snake_start.clone(),
+                direction: config.snake_direction,
+            }],
+            food: Vec::new(),
+        };
+        level.walls = level.scatter_obstacles(config.obstacle_density);
+        level
+    }
+
+    /// Single-tile obstacles that never touch each other, even diagonally, so that every free
+    /// tile stays reachable. The rows the snakes start on are kept clear.
+    fn scatter_obstacles(&self, density: f64) -> Vec<Position> {
+        let count = (self.width as f64 * self.height as f64 * density).round() as usize;
+        let start_rows: Vec<i32> = self
+            .snakes
+            .iter()
+            .flat_map(|start| start.body.iter().map(|position| position.y))
+            .collect();
+        let mut candidates: Vec<Position> = (0..self.height as i32)
+            .filter(|y| !start_rows.contains(y))
+            .flat_map(|y| (0..self.width as i32).map(move |x| Position { x, y }))
+            .collect();
+        candidates.shuffle(&mut StdRng::seed_from_u64(OBSTACLE_SEED));
+        let mut walls: Vec<Position> = Vec::new();
+        for position in candidates {
+            if walls.len() >= count {
+                break;
+            }
+            if walls
+                .iter()
+                .all(|wall| (wall.x - position.x).abs() > 1 || (wall.y - position.y).abs() > 1)
+            {
+                walls.push(position);
+            }
+        }
+        walls
+    }
+
+    pub fn load(path: &Path) -> Result<Level, String> {
+        let contents = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let name = path
+            .file_stem()
+            .map_or(DEFAULT_LEVEL_NAME.into(), |stem| stem.to_string_lossy());
+        Level::parse(&name, &contents)
+    }
+
+    /// Parses a level drawn as a grid of tiles, top row first: `#` is a wall, `*` is food,
+    /// `^`, `>`, `v` and `<` are snake heads facing up, right, down and left, `o` are body
+    /// segments (each chain connected to a head, in order, and only ever to one), and `.` or a
+    /// space is empty. A head may not face into the segment right behind it.
+    /// Heads are assigned to players from the top row down. Rows shorter than the widest one
+    /// are empty past their end, and blank lines after the last row are ignored.
+    pub fn parse(name: &str, text: &str) -> Result<Level, String> {
+        let mut rows: Vec<&str> = text.lines().map(str::trim_end).collect();
+        while rows.last().is_some_and(|row| row.is_empty()) {
+            rows.pop();
+        }
+        let height = rows.len();
+        let width = rows
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .ok_or("the level is empty")?;
+
+        let mut walls = Vec::new();
+        let mut food = Vec::new();
+        let mut body = Vec::new();
+        let mut heads = Vec::new();
+        for (row_index, row) in rows.iter().enumerate() {
+            let y = (height - 1 - row_index) as i32;
+            for (column_index, tile) in row.chars().enumerate() {
+                let position = Position {
+                    x: column_index as i32,
+                    y,
+                };
+                let direction = match tile {
+                    '.' | ' ' => continue,
+                    '#' => {
+                        walls.push(position);
+                        continue;
+                    }
+                    '*' => {
+                        food.push(position);
+                        continue;
+                    }
+                    'o' => {
+                        body.push(position);
+                        continue;
+                    }
+                    '^' => Direction::Up,
+                    '>' => Direction::Right,
+                    'v' => Direction::Down,
+                    '<' => Direction::Left,
+                    _ => {
+                        return Err(format!(
+                            "unknown tile {:?} at row {}, column {}",
+                            tile,
+                            row_index + 1,
+                            column_index + 1
+                        ))
+                    }
+                };
+                heads.push((position, direction));
+            }
+        }
+
+        if heads.is_empty() {
+            return Err("the level has no snake head".to_string());
+        }
+        let tile = |position: Position| {
+            format!(
+                "row {}, column {}",
+                height as i32 - position.y,
+                position.x + 1
+            )
+        };
+        let adjacent = |a: Position, b: Position| (a.x - b.x).abs() + (a.y - b.y).abs() == 1;
+        let mut snakes = Vec::new();
+        for &(head_position, direction) in &heads {
+            let (dx, dy) = direction.delta();
+            let ahead = Position {
+                x: head_position.x + dx,
+                y: head_position.y + dy,
+            };
+            let mut snake_body = vec![head_position];
+            loop {
+                let last = *snake_body.last().unwrap();
+                let mut next: Vec<usize> = (0..body.len())
+                    .filter(|candidate| adjacent(body[*candidate], last))
+                    .collect();
+                // A segment ahead of the head can only be the tail coiled back to it.
+                if snake_body.len() == 1 && next.len() > 1 {
+                    next.retain(|candidate| body[*candidate] != ahead);
+                }
+                let segment = match next[..] {
+                    [] => break,
+                    [segment] => segment,
+                    _ => return Err(format!("a snake body branches at {}", tile(last))),
+                };
+                if heads
+                    .iter()
+                    .any(|(other, _)| *other != head_position && adjacent(body[segment], *other))
+                {
+                    return Err(format!(
+                        "the segment at {} could belong to more than one snake",
+                        tile(body[segment])
+                    ));
+                }
+                snake_body.push(body.swap_remove(segment));
+            }
+            if snake_body.get(1) == Some(&ahead) {
+                return Err(format!(
+                    "the snake head at {} faces into its own body",
+                    tile(head_position)
+                ));
+            }
+            snakes.push(SnakeStart {
+                body: snake_body,
+                direction,
+            });
+        }
+        if !body.is_empty() {
+            return Err("a snake body is not connected to any head".to_string());
+        }
+
+        Ok(Level {
+            name: name.to_string(),
+            width: width as u32,
+            height: height as u32,
+            walls,
+            snakes,
+            food,
+        })
+    }
+
+    /// Returns a start for each player. Players beyond the starts drawn in the level get a
+    /// short horizontal snake on the first free row found around an evenly spaced row.
+    pub fn snake_starts(&self, players: usize) -> Vec<SnakeStart> {
+        let mut starts: Vec<SnakeStart> = self.snakes.iter().take(players).cloned().collect();
+        for index in starts.len()..players {
+            let is_free = |position: &Position, starts: &[SnakeStart]| {
+                !self.walls.contains(position)
+                    && !self.food.contains(position)
+                    && starts.iter().all(|start| !start.body.contains(position))
+            };
+            let (direction, column) = if index % 2 == 0 {
+                (Direction::Right, self.width as i32 / 3)
+            } else {
+                (Direction::Left, self.width as i32 * 2 / 3)
+            };
+            let preferred_row = (self.height as i32 * (index as i32 + 1)) / (players as i32 + 1);
+            let body = (0..self.height as i32)
+                .flat_map(|offset| [preferred_row + offset, preferred_row - offset - 1])
+                .filter(|row| (0..self.height as i32).contains(row))
+                .find_map(|row| {
+                    let (dx, _) = direction.opposite().delta();
+                    let body: Vec<Position> = (0..GENERATED_SNAKE_LENGTH)
+                        .map(|segment| Position {
+                            x: column + dx * segment,
+                            y: row,
+                        })
+                        .collect();
+                    let fits = body.iter().all(|position| {
+                        (0..self.width as i32).contains(&position.x) && is_free(position, &starts)
+                    });
+                    Some(body).filter(|_| fits)
+                });
+            if let Some(body) = body {
+                starts.push(SnakeStart { body, direction });
+            }
+        }
+        starts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blank_rows_inside_the_grid_are_kept() {
+        let level = Level::parse("blank", "#..#\n    \n\n.<o.\n\n\n").unwrap();
+        assert_eq!((level.width, level.height), (4, 4));
+        assert_eq!(
+            level.walls,
+            vec![Position { x: 0, y: 3 }, Position { x: 3, y: 3 }]
+        );
+        assert_eq!(
+            level.snakes[0].body,
+            vec![Position { x: 1, y: 0 }, Position { x: 2, y: 0 }]
+        );
+    }
+
+    #[test]
+    fn touching_snakes_are_ambiguous() {
+        assert!(Level::parse("touching", "<o.\n<o.\n...").is_err());
+        let level = Level::parse("apart", "<o.\n...\n<o.").unwrap();
+        assert_eq!(level.snakes.len(), 2);
+        assert!(level.snakes.iter().all(|snake| snake.body.len() == 2));
+    }
+
+    #[test]
+    fn branching_bodies_are_rejected() {
+        assert!(Level::parse("branch", ".o.\n<oo").is_err());
+    }
+
+    #[test]
+    fn head_facing_into_its_own_body_is_rejected() {
+        assert!(Level::parse("backwards", ">o.").is_err());
+        assert!(Level::parse("coiled", "vo.\noo.").is_ok());
+    }
+}
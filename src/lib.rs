@@ -0,0 +1,1563 @@
+pub mod ai;
+pub mod campaign;
+pub mod config;
+pub mod input;
+pub mod level;
+pub mod replay;
+pub mod rewind;
+pub mod simulation;
+
+use ai::{SnakeController, Strategy};
+use bevy::core::Stopwatch;
+use bevy::ecs::schedule::ShouldRun;
+use bevy::prelude::*;
+use campaign::Campaign;
+use config::{Arena, ControlScheme, Difficulty, GameConfig};
+use input::{Action, ActionInput};
+use level::Level;
+use replay::{Playback, Recording, Replay};
+use rewind::Rewind;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use simulation::{FoodKind, PendingTurns, PowerUpKind, Simulation, Snake, Steer, TickEvent, Turn};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "render")]
+use {
+    bevy::input::InputSystem,
+    campaign::FreePlayLevel,
+    input::{Bindings, Rebinding},
+    simulation::{FoodItem, PowerUpItem},
+    std::collections::HashSet,
+    std::f32::consts::FRAC_PI_4,
+};
+
+#[cfg(feature = "render")]
+const FONT: &str = "fonts/DejaVuSansMono-Bold.ttf";
+
+#[cfg(feature = "render")]
+const OVERLAY_FONT_SIZE: f32 = 24.;
+#[cfg(feature = "render")]
+const OVERLAY_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+
+#[cfg(feature = "render")]
+const HUD_FONT_SIZE: f32 = 20.;
+
+#[cfg(feature = "render")]
+const COUNTDOWN_FONT_SIZE: f32 = 12.;
+#[cfg(feature = "render")]
+const COUNTDOWN_COLOR: Color = Color::rgb(0.05, 0.05, 0.05);
+#[cfg(feature = "render")]
+const HUD_COLOR: Color = Color::rgb(0.7, 0.7, 0.7);
+#[cfg(feature = "render")]
+const HUD_MARGIN: f32 = 8.;
+
+const HIGH_SCORE_DIRECTORY: &str = "snake";
+const HIGH_SCORE_FILE: &str = "high_scores.ron";
+const HIGH_SCORE_TABLE_SIZE: usize = 10;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum GameState {
+    Menu,
+    Playing,
+    Paused,
+    GameOver,
+    LevelSelect,
+    LevelComplete,
+    Controls,
+    Replay,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Direction {
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+#[derive(Clone, Copy, Component)]
+pub struct Size {
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Component, Serialize, Deserialize)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Direction {
+    pub fn opposite(&self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Right => Direction::Left,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+        }
+    }
+
+    pub fn clockwise(&self) -> Direction {
+        match self {
+            Direction::Up => Direction::Right,
+            Direction::Right => Direction::Down,
+            Direction::Down => Direction::Left,
+            Direction::Left => Direction::Up,
+        }
+    }
+
+    pub fn counter_clockwise(&self) -> Direction {
+        self.clockwise().opposite()
+    }
+
+    pub fn delta(&self) -> (i32, i32) {
+        match self {
+            Direction::Up => (0, 1),
+            Direction::Right => (1, 0),
+            Direction::Down => (0, -1),
+            Direction::Left => (-1, 0),
+        }
+    }
+}
+
+impl Position {
+    pub fn do_move(&self, direction: Direction, arena: Arena) -> Option<Position> {
+        let (dx, dy) = direction.delta();
+        arena.confine(Position {
+            x: self.x + dx,
+            y: self.y + dy,
+        })
+    }
+}
+
+/// Identifies which snake an event or a segment sprite is about, by index into the simulated
+/// snakes.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Component)]
+pub struct SnakeId(pub usize);
+
+/// Sprite mirroring one segment of the snake its `SnakeId` names, the head being index 0.
+#[derive(Component)]
+pub struct SnakeSegment {
+    pub index: usize,
+}
+
+/// Marks the sprite of each snake's head.
+#[derive(Component)]
+pub struct SnakeHead;
+
+#[derive(Component)]
+pub struct Food {
+    pub kind: FoodKind,
+}
+
+#[derive(Component)]
+pub struct PowerUp {
+    pub kind: PowerUpKind,
+}
+
+/// Seconds left before the food on the same tile disappears.
+#[derive(Component)]
+pub struct FoodCountdown;
+
+#[derive(Component)]
+pub struct Obstacle;
+
+#[derive(Component)]
+pub struct ArenaBackground;
+
+#[derive(Component)]
+pub struct Overlay;
+
+#[derive(Component)]
+pub struct Hud;
+
+/// Sent when a snake eats food that makes it longer, with the tile its head took.
+pub struct GrowEvent {
+    pub snake: SnakeId,
+    pub position: Position,
+}
+
+pub struct ScoreEvent {
+    pub snake: SnakeId,
+    pub points: u32,
+}
+
+pub struct DeathEvent {
+    pub snake: SnakeId,
+}
+
+#[derive(Clone)]
+pub struct PlayerScore {
+    pub name: String,
+    pub controller: SnakeController,
+    pub points: u32,
+    pub eaten: u32,
+    pub length: u32,
+    pub alive: bool,
+}
+
+impl PlayerScore {
+    pub fn is_bot(&self) -> bool {
+        matches!(self.controller, SnakeController::Bot(_))
+    }
+}
+
+/// Each snake's score, mirrored from the simulation for apps embedding the game.
+#[derive(Default)]
+pub struct Score {
+    pub players: Vec<PlayerScore>,
+}
+
+#[derive(Default)]
+pub struct RunTime {
+    pub stopwatch: Stopwatch,
+}
+
+pub struct Player {
+    pub name: String,
+}
+
+impl Default for Player {
+    fn default() -> Self {
+        Player {
+            name: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .unwrap_or_else(|_| "player".to_string()),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct HighScoreVariant {
+    #[serde(default)]
+    pub level: String,
+    pub arena: Arena,
+    pub move_step: f64,
+    #[serde(default = "single_player")]
+    pub players: usize,
+    #[serde(default)]
+    pub bots: Vec<Strategy>,
+    #[serde(default)]
+    pub difficulty: Difficulty,
+}
+
+fn single_player() -> usize {
+    1
+}
+
+impl HighScoreVariant {
+    pub fn current(config: &GameConfig, level: &Level) -> HighScoreVariant {
+        HighScoreVariant {
+            level: level.name.clone(),
+            arena: config.arena,
+            move_step: config.move_step,
+            players: config.players,
+            bots: config.bots.clone(),
+            difficulty: config.difficulty,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HighScoreEntry {
+    pub player: String,
+    pub score: u32,
+    pub length: u32,
+    pub duration: f32,
+    pub seed: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HighScoreTable {
+    pub variant: HighScoreVariant,
+    pub entries: Vec<HighScoreEntry>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct HighScores {
+    pub tables: Vec<HighScoreTable>,
+    #[serde(skip)]
+    pub last_ranks: Vec<usize>,
+}
+
+impl HighScores {
+    pub fn path() -> Option<PathBuf> {
+        dirs::data_dir().map(|data_dir| data_dir.join(HIGH_SCORE_DIRECTORY).join(HIGH_SCORE_FILE))
+    }
+
+    pub fn load() -> HighScores {
+        HighScores::path()
+            .and_then(|path| load_ron(&path, "high scores"))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Some(path) = HighScores::path() {
+            save_ron(&path, self, "high scores");
+        }
+    }
+
+    pub fn entries(&self, variant: &HighScoreVariant) -> &[HighScoreEntry] {
+        self.tables
+            .iter()
+            .find(|table| table.variant == *variant)
+            .map(|table| table.entries.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn insert(&mut self, variant: HighScoreVariant, entry: HighScoreEntry) -> Option<usize> {
+        let table = match self
+            .tables
+            .iter()
+            .position(|table| table.variant == variant)
+        {
+            Some(index) => &mut self.tables[index],
+            None => {
+                self.tables.push(HighScoreTable {
+                    variant,
+                    entries: Vec::new(),
+                });
+                self.tables.last_mut().unwrap()
+            }
+        };
+        let rank = table
+            .entries
+            .iter()
+            .position(|other| entry.score > other.score)
+            .unwrap_or(table.entries.len());
+        if rank >= HIGH_SCORE_TABLE_SIZE {
+            return None;
+        }
+        table.entries.insert(rank, entry);
+        table.entries.truncate(HIGH_SCORE_TABLE_SIZE);
+        Some(rank)
+    }
+}
+
+pub(crate) fn read_ron<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let contents = fs::read_to_string(path).map_err(|error| error.to_string())?;
+    ron::from_str(&contents).map_err(|error| error.to_string())
+}
+
+/// Reads a saved file if there is one, warning about and ignoring it when unreadable.
+pub(crate) fn load_ron<T: DeserializeOwned>(path: &Path, what: &str) -> Option<T> {
+    if !path.exists() {
+        return None;
+    }
+    match read_ron(path) {
+        Ok(value) => Some(value),
+        Err(error) => {
+            warn!("ignoring unreadable {} at {:?}: {}", what, path, error);
+            None
+        }
+    }
+}
+
+/// Writes a file along with its directory, warning when that fails.
+pub(crate) fn save_ron<T: Serialize>(path: &Path, value: &T, what: &str) {
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .map_err(|error| error.to_string())
+        .and_then(|_| {
+            ron::ser::to_string_pretty(value, default()).map_err(|error| error.to_string())
+        })
+        .and_then(|contents| fs::write(path, contents).map_err(|error| error.to_string()));
+    if let Err(error) = result {
+        warn!("failed to save {} to {:?}: {}", what, path, error);
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn spawn_arena(mut commands: Commands, config: Res<GameConfig>) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: config.arena_color,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(ArenaBackground);
+}
+
+fn tile_size(window: &Window, arena: Arena) -> f32 {
+    (window.width() / arena.width as f32).min(window.height() / arena.height as f32)
+}
+
+pub fn scale_arena(
+    simulation: Res<Simulation>,
+    windows: Res<Windows>,
+    mut query: Query<&mut Transform, With<ArenaBackground>>,
+) {
+    let window = windows.get_primary().unwrap();
+    let tile_size = tile_size(window, simulation.arena);
+    for mut transform in query.iter_mut() {
+        transform.translation = Vec3::new(0., 0., -1.);
+        transform.scale = Vec3::new(
+            simulation.arena.width as f32 * tile_size,
+            simulation.arena.height as f32 * tile_size,
+            1.,
+        );
+    }
+}
+
+pub fn translate_position(
+    simulation: Res<Simulation>,
+    windows: Res<Windows>,
+    mut query: Query<(&Position, &mut Transform)>,
+) {
+    let window = windows.get_primary().unwrap();
+    let tile_size = tile_size(window, simulation.arena);
+    let origin = Vec2::new(
+        -(simulation.arena.width as f32) * tile_size / 2. + tile_size / 2.,
+        -(simulation.arena.height as f32) * tile_size / 2. + tile_size / 2.,
+    );
+    for (position, mut transform) in query.iter_mut() {
+        transform.translation = Vec3::new(
+            origin.x + position.x as f32 * tile_size,
+            origin.y + position.y as f32 * tile_size,
+            transform.translation.z,
+        );
+    }
+}
+
+pub fn scale_size(
+    simulation: Res<Simulation>,
+    windows: Res<Windows>,
+    mut query: Query<(&Size, &mut Transform)>,
+) {
+    let window = windows.get_primary().unwrap();
+    let tile_size = tile_size(window, simulation.arena);
+    for (size, mut transform) in query.iter_mut() {
+        transform.scale = Vec3::new(size.width * tile_size, size.height * tile_size, 1.);
+    }
+}
+
+#[cfg(feature = "render")]
+fn spawn_snake_segment(
+    commands: &mut Commands,
+    config: &GameConfig,
+    snake: usize,
+    index: usize,
+    position: Position,
+) {
+    let colors = config.snake_colors(snake);
+    let (color, size) = if index == 0 {
+        (colors.head, config.snake_head_size)
+    } else {
+        (colors.segment, config.snake_segment_size)
+    };
+    let mut entity = commands.spawn_bundle(SpriteBundle {
+        sprite: Sprite { color, ..default() },
+        ..default()
+    });
+    entity
+        .insert(position)
+        .insert(Size {
+            width: size,
+            height: size,
+        })
+        .insert(SnakeId(snake))
+        .insert(SnakeSegment { index });
+    if index == 0 {
+        entity.insert(SnakeHead);
+    }
+}
+
+/// Mirrors the simulated snakes with sprites. Dead snakes leave the board, except when they
+/// ended the round.
+#[cfg(feature = "render")]
+pub fn sync_snakes(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    simulation: Res<Simulation>,
+    mut snake_segment_query: Query<(Entity, &SnakeId, &SnakeSegment, &mut Position)>,
+) {
+    if !simulation.is_changed() {
+        return;
+    }
+    let is_shown = |snake: &Snake| snake.alive || simulation.is_over();
+    let mut shown = HashSet::new();
+    for (entity, SnakeId(snake_index), snake_segment, mut position) in
+        snake_segment_query.iter_mut()
+    {
+        match simulation
+            .snakes
+            .get(*snake_index)
+            .filter(|snake| is_shown(snake))
+            .and_then(|snake| snake.body.get(snake_segment.index))
+        {
+            Some(segment_position) => {
+                *position = *segment_position;
+                shown.insert((*snake_index, snake_segment.index));
+            }
+            None => commands.entity(entity).despawn(),
+        }
+    }
+    for (snake_index, snake) in simulation.snakes.iter().enumerate() {
+        if !is_shown(snake) {
+            continue;
+        }
+        for (index, position) in snake.body.iter().enumerate() {
+            if !shown.contains(&(snake_index, index)) {
+                spawn_snake_segment(&mut commands, &config, snake_index, index, *position);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+fn spawn_food_at(commands: &mut Commands, config: &GameConfig, food: &FoodItem) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: config.food_color(food.kind),
+                ..default()
+            },
+            ..default()
+        })
+        .insert(food.position)
+        .insert(Size {
+            width: config.food_size,
+            height: config.food_size,
+        })
+        .insert(Food { kind: food.kind });
+}
+
+#[cfg(feature = "render")]
+pub fn sync_food(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    simulation: Res<Simulation>,
+    food_query: Query<(Entity, &Position, &Food)>,
+) {
+    if !simulation.is_changed() {
+        return;
+    }
+    let mut shown = Vec::new();
+    for (entity, position, food) in food_query.iter() {
+        if simulation
+            .food
+            .iter()
+            .any(|item| item.position == *position && item.kind == food.kind)
+            && !shown.contains(position)
+        {
+            shown.push(*position);
+        } else {
+            commands.entity(entity).despawn();
+        }
+    }
+    for food in simulation.food.iter() {
+        if !shown.contains(&food.position) {
+            spawn_food_at(&mut commands, &config, food);
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+fn spawn_power_up_at(commands: &mut Commands, config: &GameConfig, power_up: &PowerUpItem) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: config.power_up_color(power_up.kind),
+                ..default()
+            },
+            // Turned on a corner to tell power-ups apart from food.
+            transform: Transform::from_rotation(Quat::from_rotation_z(FRAC_PI_4)),
+            ..default()
+        })
+        .insert(power_up.position)
+        .insert(Size {
+            width: config.power_up_size,
+            height: config.power_up_size,
+        })
+        .insert(PowerUp {
+            kind: power_up.kind,
+        });
+}
+
+#[cfg(feature = "render")]
+pub fn sync_power_ups(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    simulation: Res<Simulation>,
+    power_up_query: Query<(Entity, &Position, &PowerUp)>,
+) {
+    if !simulation.is_changed() {
+        return;
+    }
+    let mut shown = Vec::new();
+    for (entity, position, power_up) in power_up_query.iter() {
+        if simulation
+            .power_ups
+            .iter()
+            .any(|item| item.position == *position && item.kind == power_up.kind)
+            && !shown.contains(position)
+        {
+            shown.push(*position);
+        } else {
+            commands.entity(entity).despawn();
+        }
+    }
+    for power_up in simulation.power_ups.iter() {
+        if !shown.contains(&power_up.position) {
+            spawn_power_up_at(&mut commands, &config, power_up);
+        }
+    }
+}
+
+/// Shows the seconds left on food that disappears when left uneaten.
+#[cfg(feature = "render")]
+pub fn sync_food_countdowns(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    tick_rate: Res<TickRate>,
+    simulation: Res<Simulation>,
+    mut countdown_query: Query<(Entity, &Position, &mut Text), With<FoodCountdown>>,
+) {
+    if !simulation.is_changed() {
+        return;
+    }
+    let seconds_left = |position: Position| {
+        simulation
+            .food
+            .iter()
+            .find(|food| food.position == position)
+            .and_then(|food| simulation.food_seconds_left(food, tick_rate.step(&simulation)))
+            .map(|seconds| format!("{}", seconds.ceil()))
+    };
+    let mut shown = Vec::new();
+    for (entity, position, mut text) in countdown_query.iter_mut() {
+        match seconds_left(*position).filter(|_| !shown.contains(position)) {
+            Some(seconds) => {
+                text.sections[0].value = seconds;
+                shown.push(*position);
+            }
+            None => commands.entity(entity).despawn(),
+        }
+    }
+    for food in simulation.food.iter() {
+        if let (false, Some(seconds)) =
+            (shown.contains(&food.position), seconds_left(food.position))
+        {
+            commands
+                .spawn_bundle(Text2dBundle {
+                    text: Text::with_section(
+                        seconds,
+                        TextStyle {
+                            font: asset_server.load(FONT),
+                            font_size: COUNTDOWN_FONT_SIZE,
+                            color: COUNTDOWN_COLOR,
+                        },
+                        TextAlignment {
+                            vertical: VerticalAlign::Center,
+                            horizontal: HorizontalAlign::Center,
+                        },
+                    ),
+                    transform: Transform::from_xyz(0., 0., 1.),
+                    ..default()
+                })
+                .insert(food.position)
+                .insert(FoodCountdown);
+        }
+    }
+}
+
+pub fn sync_score(simulation: Res<Simulation>, mut score: ResMut<Score>) {
+    if !simulation.is_changed() {
+        return;
+    }
+    score.players = simulation
+        .snakes
+        .iter()
+        .map(|snake| PlayerScore {
+            name: snake.name.clone(),
+            controller: snake.controller,
+            points: snake.points,
+            eaten: snake.eaten,
+            length: snake.length(),
+            alive: snake.alive,
+        })
+        .collect();
+}
+
+/// Re-sends what happened on each tick as the growth, score and death events.
+pub fn send_snake_events(
+    mut tick_event_reader: EventReader<TickEvent>,
+    mut grow_event_writer: EventWriter<GrowEvent>,
+    mut score_event_writer: EventWriter<ScoreEvent>,
+    mut death_event_writer: EventWriter<DeathEvent>,
+) {
+    for tick_event in tick_event_reader.iter() {
+        match *tick_event {
+            TickEvent::Ate {
+                snake,
+                position,
+                kind,
+                points,
+            } => {
+                if points > 0 {
+                    score_event_writer.send(ScoreEvent {
+                        snake: SnakeId(snake),
+                        points,
+                    });
+                }
+                if !matches!(kind, FoodKind::Shrink | FoodKind::Poison) {
+                    grow_event_writer.send(GrowEvent {
+                        snake: SnakeId(snake),
+                        position,
+                    });
+                }
+            }
+            TickEvent::Died { snake } => {
+                death_event_writer.send(DeathEvent {
+                    snake: SnakeId(snake),
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn spawn_level(mut commands: Commands, config: Res<GameConfig>, simulation: Res<Simulation>) {
+    for position in simulation.walls.iter() {
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: config.obstacle_color,
+                    ..default()
+                },
+                ..default()
+            })
+            .insert(*position)
+            .insert(Size {
+                width: config.obstacle_size,
+                height: config.obstacle_size,
+            })
+            .insert(Obstacle);
+    }
+}
+
+pub fn handle_input(
+    config: Res<GameConfig>,
+    actions: Res<ActionInput>,
+    simulation: Res<Simulation>,
+    mut pending_turns: ResMut<PendingTurns>,
+) {
+    let steers = match config.control_scheme {
+        ControlScheme::Absolute => vec![
+            (Action::TurnUp, Steer::Toward(Direction::Up)),
+            (Action::TurnRight, Steer::Toward(Direction::Right)),
+            (Action::TurnDown, Steer::Toward(Direction::Down)),
+            (Action::TurnLeft, Steer::Toward(Direction::Left)),
+        ],
+        ControlScheme::Relative => vec![
+            (Action::TurnCounterClockwise, Steer::CounterClockwise),
+            (Action::TurnClockwise, Steer::Clockwise),
+        ],
+    };
+    for (index, snake) in simulation.snakes.iter().enumerate() {
+        let player = match snake.controller {
+            SnakeController::Player(player) => player,
+            SnakeController::Bot(_) => continue,
+        };
+        for (action, steer) in steers.iter().copied() {
+            // A lone player may use any of the shared bindings, otherwise each snake listens
+            // only to its own player's bindings.
+            let just_pressed = if config.players == 1 {
+                actions.just_pressed(action)
+            } else {
+                actions.player_just_pressed(player, action)
+            };
+            if just_pressed {
+                pending_turns.turns.push(Turn {
+                    snake: index,
+                    steer,
+                });
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn start_simulation(
+    config: Res<GameConfig>,
+    level: Res<Level>,
+    campaign: Option<Res<Campaign>>,
+    mut simulation: ResMut<Simulation>,
+    mut pending_turns: ResMut<PendingTurns>,
+    mut recording: ResMut<Recording>,
+    mut run_time: ResMut<RunTime>,
+    mut tick_rate: ResMut<TickRate>,
+) {
+    let carried_points = campaign
+        .as_ref()
+        .map(|campaign| campaign.carried_points.as_slice())
+        .unwrap_or_default();
+    let seed = config.seed.unwrap_or_else(rand::random);
+    *simulation = Simulation::new(&config, &level, carried_points, seed);
+    if config.bots.contains(&Strategy::Hamiltonian)
+        && ai::hamiltonian_cycle(simulation.arena, &simulation.walls).is_none()
+    {
+        warn!("no cycle goes through every tile of this arena, hamiltonian bots will pathfind");
+    }
+    recording.replay = Replay::new(&config, &level, carried_points, seed);
+    pending_turns.turns.clear();
+    run_time.stopwatch.reset();
+    tick_rate.accumulator = 0.;
+}
+
+/// Ticks the simulation with the pending turns. A round that ends goes to `GameOver`, or first
+/// pauses when rewinding is on, so that the ticks leading up to the end can be stepped through.
+pub fn advance_simulation(
+    mut game_state: ResMut<State<GameState>>,
+    mut simulation: ResMut<Simulation>,
+    mut pending_turns: ResMut<PendingTurns>,
+    mut recording: ResMut<Recording>,
+    rewind: Res<Rewind>,
+    mut tick_event_writer: EventWriter<TickEvent>,
+) {
+    let turns = std::mem::take(&mut pending_turns.turns);
+    for tick_event in simulation.tick(&turns) {
+        tick_event_writer.send(tick_event);
+    }
+    recording.replay.record(simulation.ticks, &turns);
+    if simulation.is_over() {
+        if rewind.is_enabled() {
+            game_state.overwrite_push(GameState::Paused).unwrap();
+        } else {
+            game_state.overwrite_set(GameState::GameOver).unwrap();
+        }
+    }
+}
+
+pub fn update_run_time(time: Res<Time>, mut run_time: ResMut<RunTime>) {
+    run_time.stopwatch.tick(time.delta());
+}
+
+#[cfg(feature = "render")]
+pub fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(HUD_MARGIN),
+                    top: Val::Px(HUD_MARGIN),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load(FONT),
+                    font_size: HUD_FONT_SIZE,
+                    color: HUD_COLOR,
+                },
+                default(),
+            ),
+            ..default()
+        })
+        .insert(Hud);
+}
+
+#[cfg(feature = "render")]
+pub fn update_hud(
+    tick_rate: Res<TickRate>,
+    campaign: Res<Campaign>,
+    simulation: Res<Simulation>,
+    run_time: Res<RunTime>,
+    mut hud_query: Query<&mut Text, With<Hud>>,
+) {
+    let elapsed = run_time.stopwatch.elapsed_secs();
+    let mut text = hud_query.single_mut();
+    text.sections[0].value = format!(
+        "{}   time {:.1}   seed {}",
+        simulation.describe_scores(),
+        elapsed,
+        simulation.seed
+    );
+    if let Some(goal) = campaign.active_goal() {
+        text.sections[0].value += &format!(
+            "   {}",
+            goal.progress(simulation.most_eaten(), simulation.longest(), elapsed)
+        );
+    }
+    if simulation.tick_rate_factor() != 1. {
+        text.sections[0].value += &format!("   speed x{:.2}", simulation.tick_rate_factor());
+    }
+    let effects: Vec<String> = simulation
+        .snakes
+        .iter()
+        .filter(|snake| snake.alive && !snake.effects.is_empty())
+        .map(|snake| {
+            let effects: Vec<String> = simulation
+                .effects_left(snake)
+                .iter()
+                .map(|(kind, ticks)| {
+                    format!(
+                        "{} {:.0}s",
+                        kind.name(),
+                        (*ticks as f64 * tick_rate.step(&simulation)).ceil()
+                    )
+                })
+                .collect();
+            format!("{}: {}", snake.name, effects.join(", "))
+        })
+        .collect();
+    if !effects.is_empty() {
+        text.sections[0].value += &format!("\n{}", effects.join("   "));
+    }
+}
+
+#[cfg(feature = "render")]
+#[allow(clippy::too_many_arguments)]
+pub fn handle_menu_input(
+    mut actions: ResMut<ActionInput>,
+    mut game_state: ResMut<State<GameState>>,
+    bindings: Res<Bindings>,
+    mut config: ResMut<GameConfig>,
+    mut level: ResMut<Level>,
+    mut free_play_level: ResMut<FreePlayLevel>,
+    mut campaign: ResMut<Campaign>,
+    mut overlay_query: Query<&mut Text, With<Overlay>>,
+) {
+    if actions.just_pressed(Action::TurnLeft) || actions.just_pressed(Action::TurnRight) {
+        let difficulty = config
+            .difficulty
+            .cycle(actions.just_pressed(Action::TurnRight));
+        // The config file or command line may not fit every preset, which then stays out.
+        let mut candidate = config.clone();
+        difficulty.apply(&mut candidate);
+        let notice = match candidate.validate() {
+            Ok(()) => {
+                *config = candidate;
+                if free_play_level.generated {
+                    free_play_level.level = Level::open(&config);
+                }
+                String::new()
+            }
+            Err(error) => format!(
+                "\n\n{} is unavailable: {}",
+                difficulty.name().to_uppercase(),
+                error
+            ),
+        };
+        for mut text in overlay_query.iter_mut() {
+            text.sections[0].value = menu_text(&bindings, &campaign, &config) + &notice;
+        }
+    } else if actions.just_pressed(Action::Confirm) {
+        actions.consume(Action::Confirm);
+        campaign::start_free_play(&mut config, &mut level, &free_play_level, &mut campaign);
+        game_state.set(GameState::Playing).unwrap();
+    } else if actions.just_pressed(Action::Campaign) && !campaign.stages.is_empty() {
+        actions.consume(Action::Campaign);
+        game_state.set(GameState::LevelSelect).unwrap();
+    } else if actions.just_pressed(Action::Controls) {
+        actions.consume(Action::Controls);
+        game_state.set(GameState::Controls).unwrap();
+    }
+}
+
+pub fn handle_pause_input(
+    mut actions: ResMut<ActionInput>,
+    mut game_state: ResMut<State<GameState>>,
+    simulation: Res<Simulation>,
+) {
+    if actions.just_pressed(Action::Pause) {
+        actions.consume(Action::Pause);
+        if *game_state.current() == GameState::Paused {
+            // Paused on the tick that ended the round, which only rewinding can take back.
+            if simulation.is_over() {
+                game_state.replace(GameState::GameOver).unwrap();
+            } else {
+                game_state.pop().unwrap();
+            }
+        } else {
+            game_state.push(GameState::Paused).unwrap();
+        }
+    }
+}
+
+pub fn handle_game_over_input(
+    mut actions: ResMut<ActionInput>,
+    mut game_state: ResMut<State<GameState>>,
+    recording: Res<Recording>,
+    mut playback: ResMut<Playback>,
+) {
+    if actions.just_pressed(Action::Restart) {
+        actions.consume(Action::Restart);
+        game_state.set(GameState::Playing).unwrap();
+    } else if actions.just_pressed(Action::Replay) {
+        actions.consume(Action::Replay);
+        playback.replay = Some(recording.replay.clone());
+        game_state.set(GameState::Replay).unwrap();
+    } else if actions.just_pressed(Action::Back) {
+        actions.consume(Action::Back);
+        game_state.set(GameState::Menu).unwrap();
+    }
+}
+
+/// Everything spawned for a round, to clear the board when it ends.
+type GameEntityFilter = Or<(
+    With<SnakeSegment>,
+    With<Food>,
+    With<FoodCountdown>,
+    With<PowerUp>,
+    With<Obstacle>,
+    With<Hud>,
+)>;
+
+pub fn despawn_game(mut commands: Commands, query: Query<Entity, GameEntityFilter>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+#[cfg(feature = "render")]
+pub(crate) fn spawn_overlay(commands: &mut Commands, asset_server: &AssetServer, text: &str) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                margin: Rect::all(Val::Auto),
+                ..default()
+            },
+            text: Text::with_section(
+                text,
+                TextStyle {
+                    font: asset_server.load(FONT),
+                    font_size: OVERLAY_FONT_SIZE,
+                    color: OVERLAY_COLOR,
+                },
+                TextAlignment {
+                    vertical: VerticalAlign::Center,
+                    horizontal: HorizontalAlign::Center,
+                },
+            ),
+            ..default()
+        })
+        .insert(Overlay);
+}
+
+#[cfg(feature = "render")]
+fn menu_text(bindings: &Bindings, campaign: &Campaign, config: &GameConfig) -> String {
+    let mut text = format!(
+        "SNAKE\n\n{}/{} difficulty: {}\n\npress {} to start",
+        bindings.describe(Action::TurnLeft),
+        bindings.describe(Action::TurnRight),
+        config.difficulty.name().to_uppercase(),
+        bindings.describe(Action::Confirm)
+    );
+    if !campaign.stages.is_empty() {
+        text += &format!(
+            "\npress {} for campaign",
+            bindings.describe(Action::Campaign)
+        );
+    }
+    text += &format!(
+        "\npress {} for controls",
+        bindings.describe(Action::Controls)
+    );
+    text
+}
+
+#[cfg(feature = "render")]
+pub fn spawn_menu_overlay(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    bindings: Res<Bindings>,
+    campaign: Res<Campaign>,
+    config: Res<GameConfig>,
+) {
+    spawn_overlay(
+        &mut commands,
+        &asset_server,
+        &menu_text(&bindings, &campaign, &config),
+    );
+}
+
+#[cfg(feature = "render")]
+pub fn spawn_pause_overlay(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    bindings: Res<Bindings>,
+    rewind: Res<Rewind>,
+    simulation: Res<Simulation>,
+) {
+    spawn_overlay(
+        &mut commands,
+        &asset_server,
+        &rewind::pause_text(&bindings, &rewind, &simulation),
+    );
+}
+
+pub fn record_high_score(
+    config: Res<GameConfig>,
+    level: Res<Level>,
+    player: Res<Player>,
+    simulation: Res<Simulation>,
+    run_time: Res<RunTime>,
+    mut high_scores: ResMut<HighScores>,
+) {
+    high_scores.last_ranks.clear();
+    // Rounds that could be stepped back through and replayed don't make the table.
+    if config.debug_rewind {
+        return;
+    }
+    let variant = HighScoreVariant::current(&config, &level);
+    let mut snakes: Vec<&Snake> = simulation
+        .snakes
+        .iter()
+        .filter(|snake| !snake.is_bot())
+        .collect();
+    snakes.sort_by_key(|snake| std::cmp::Reverse(snake.points));
+    // Entries go in best first, so later insertions never shift the ranks already recorded.
+    for snake in snakes {
+        let entry = HighScoreEntry {
+            player: if config.players == 1 {
+                player.name.clone()
+            } else {
+                snake.name.clone()
+            },
+            score: snake.points,
+            length: snake.length(),
+            duration: run_time.stopwatch.elapsed_secs(),
+            seed: Some(simulation.seed),
+        };
+        if let Some(rank) = high_scores.insert(variant.clone(), entry) {
+            high_scores.last_ranks.push(rank);
+        }
+    }
+    high_scores.save();
+}
+
+#[cfg(feature = "render")]
+pub fn spawn_game_over_overlay(
+    mut commands: Commands,
+    bindings: Res<Bindings>,
+    config: Res<GameConfig>,
+    level: Res<Level>,
+    asset_server: Res<AssetServer>,
+    simulation: Res<Simulation>,
+    high_scores: Res<HighScores>,
+) {
+    let survivors: Vec<&Snake> = simulation
+        .snakes
+        .iter()
+        .filter(|snake| snake.alive)
+        .collect();
+    let mut text = match survivors.as_slice() {
+        _ if simulation.board_full => "BOARD FULL - VICTORY".to_string(),
+        _ if config.snakes() == 1 => "GAME OVER".to_string(),
+        [] => "DRAW".to_string(),
+        [winner] => format!("{} WINS", winner.name.to_uppercase()),
+        _ => "GAME OVER".to_string(),
+    };
+    text += &format!(
+        "\n\n{} high scores\n #  player      score  length    time\n",
+        config.difficulty.name()
+    );
+    for (rank, entry) in high_scores
+        .entries(&HighScoreVariant::current(&config, &level))
+        .iter()
+        .enumerate()
+    {
+        text += &format!(
+            "{}{:>2}  {:<10} {:>6} {:>7} {:>7.1}\n",
+            if high_scores.last_ranks.contains(&rank) {
+                '>'
+            } else {
+                ' '
+            },
+            rank + 1,
+            entry.player.chars().take(10).collect::<String>(),
+            entry.score,
+            entry.length,
+            entry.duration,
+        );
+    }
+    text += &format!(
+        "\npress {} to restart\npress {} to watch the replay\npress {} for menu",
+        bindings.describe(Action::Restart),
+        bindings.describe(Action::Replay),
+        bindings.describe(Action::Back),
+    );
+    spawn_overlay(&mut commands, &asset_server, &text);
+}
+
+pub fn despawn_overlay(mut commands: Commands, query: Query<Entity, With<Overlay>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// How often the simulation ticks. The base step follows the config, even when it changes
+/// mid-round, and the simulation speeds it up with its ramp and speed effects.
+pub struct TickRate {
+    /// Seconds between two ticks before any speed change.
+    pub base_step: f64,
+    /// Time accumulated towards the next tick.
+    pub accumulator: f64,
+    /// Whether the criterion is being checked again within the same frame.
+    pub looping: bool,
+    /// Ticks once per update instead of following the clock, to run headless rounds as fast
+    /// as possible.
+    pub unthrottled: bool,
+}
+
+impl TickRate {
+    pub fn new(base_step: f64) -> TickRate {
+        TickRate {
+            base_step,
+            accumulator: 0.,
+            looping: false,
+            unthrottled: false,
+        }
+    }
+
+    /// Seconds between two ticks of a simulation at its current speed.
+    pub fn step(&self, simulation: &Simulation) -> f64 {
+        self.base_step / simulation.tick_rate_factor()
+    }
+
+    /// Whether a tick is due after a frame lasting `delta` seconds, checked again within the
+    /// same frame for as long as it still has ticks to catch up on.
+    pub fn poll(&mut self, simulation: &Simulation, delta: f64) -> ShouldRun {
+        if !self.looping {
+            self.accumulator += delta;
+        }
+        let step = self.step(simulation);
+        if self.accumulator >= step {
+            self.accumulator -= step;
+            self.looping = true;
+            ShouldRun::YesAndCheckAgain
+        } else {
+            self.looping = false;
+            ShouldRun::No
+        }
+    }
+}
+
+pub fn update_base_step(config: Res<GameConfig>, mut tick_rate: ResMut<TickRate>) {
+    if config.is_changed() {
+        tick_rate.base_step = config.move_step;
+    }
+}
+
+/// Runs the simulation once per tick step while playing, catching up on frames longer than a
+/// step.
+pub fn run_on_tick(
+    time: Res<Time>,
+    game_state: Res<State<GameState>>,
+    simulation: Res<Simulation>,
+    mut tick_rate: ResMut<TickRate>,
+) -> ShouldRun {
+    // A finished round is still `Playing` until the state change is applied, which may be a
+    // few catch-up ticks later.
+    if *game_state.current() != GameState::Playing || simulation.is_over() {
+        tick_rate.looping = false;
+        return ShouldRun::No;
+    }
+    if tick_rate.unthrottled {
+        return ShouldRun::Yes;
+    }
+    tick_rate.poll(&simulation, time.delta_seconds_f64())
+}
+
+/// The game rules without rendering, windowing or input, enough to run rounds headless under
+/// `MinimalPlugins`. Starts straight into `GameState::Playing` unless a state was added before,
+/// and keeps a `TickRate` added before.
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        let config = app
+            .world
+            .get_resource_or_insert_with(GameConfig::default)
+            .clone();
+        if !app.world.contains_resource::<Level>() {
+            app.insert_resource(Level::open(&config));
+        }
+        if !app.world.contains_resource::<State<GameState>>() {
+            app.add_state(GameState::Playing);
+        }
+        if !app.world.contains_resource::<TickRate>() {
+            app.insert_resource(TickRate::new(config.move_step));
+        }
+        let level = app.world.get_resource::<Level>().unwrap().clone();
+        let seed = config.seed.unwrap_or_default();
+        app.add_event::<TickEvent>()
+            .insert_resource(Simulation::new(&config, &level, &[], seed))
+            .insert_resource(Recording {
+                replay: Replay::new(&config, &level, &[], seed),
+            })
+            .init_resource::<PendingTurns>()
+            .init_resource::<RunTime>()
+            .init_resource::<Rewind>()
+            .add_system(update_base_step)
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing)
+                    .with_system(start_simulation)
+                    .with_system(rewind::reset_rewind.after(start_simulation)),
+            )
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(update_run_time))
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(run_on_tick)
+                    .with_system(ai::steer_bots)
+                    .with_system(advance_simulation.after(ai::steer_bots))
+                    .with_system(rewind::record_snapshot.after(advance_simulation)),
+            );
+    }
+}
+
+/// The full game on top of `SimulationPlugin`: menus, sprites, HUD and input. The app spawns
+/// its own 2D and UI cameras.
+#[cfg(feature = "render")]
+pub struct SnakePlugin;
+
+#[cfg(feature = "render")]
+impl Plugin for SnakePlugin {
+    fn build(&self, app: &mut App) {
+        let generated = !app.world.contains_resource::<Level>();
+        app.add_state(GameState::Menu).add_plugin(SimulationPlugin);
+        let level = app.world.get_resource::<Level>().unwrap().clone();
+        if !app.world.contains_resource::<Bindings>() {
+            app.insert_resource(Bindings::load());
+        }
+        app.init_resource::<Player>()
+            .init_resource::<Playback>()
+            .init_resource::<Campaign>()
+            .init_resource::<ActionInput>()
+            .init_resource::<Rebinding>()
+            .insert_resource(FreePlayLevel { level, generated })
+            .insert_resource(HighScores::load())
+            .init_resource::<Score>()
+            .add_event::<GrowEvent>()
+            .add_event::<ScoreEvent>()
+            .add_event::<DeathEvent>()
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                input::update_actions.after(InputSystem),
+            )
+            .add_startup_system(spawn_arena)
+            .add_startup_system(replay::watch_startup_replay)
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(spawn_menu_overlay))
+            .add_system_set(SystemSet::on_update(GameState::Menu).with_system(handle_menu_input))
+            .add_system_set(SystemSet::on_exit(GameState::Menu).with_system(despawn_overlay))
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing)
+                    .with_system(spawn_level.after(start_simulation))
+                    .with_system(spawn_hud)
+                    .with_system(sync_snakes.after(start_simulation))
+                    .with_system(sync_food.after(start_simulation))
+                    .with_system(sync_power_ups.after(start_simulation))
+                    .with_system(sync_food_countdowns.after(start_simulation)),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(
+                        handle_pause_input
+                            .before(advance_simulation)
+                            .before(campaign::check_goal),
+                    )
+                    .with_system(handle_input.before(advance_simulation))
+                    .with_system(sync_snakes.after(advance_simulation))
+                    .with_system(sync_food.after(advance_simulation))
+                    .with_system(sync_power_ups.after(advance_simulation))
+                    .with_system(sync_food_countdowns.after(advance_simulation))
+                    .with_system(update_hud.after(advance_simulation).after(update_run_time))
+                    .with_system(
+                        campaign::check_goal
+                            .after(advance_simulation)
+                            .after(update_run_time),
+                    ),
+            )
+            .add_system_set(SystemSet::on_enter(GameState::Paused).with_system(spawn_pause_overlay))
+            .add_system_set(
+                SystemSet::on_update(GameState::Paused)
+                    .with_system(handle_pause_input)
+                    .with_system(rewind::handle_rewind_input)
+                    .with_system(sync_snakes.after(rewind::handle_rewind_input))
+                    .with_system(sync_food.after(rewind::handle_rewind_input))
+                    .with_system(sync_power_ups.after(rewind::handle_rewind_input))
+                    .with_system(sync_food_countdowns.after(rewind::handle_rewind_input))
+                    .with_system(update_hud.after(rewind::handle_rewind_input)),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Paused)
+                    .with_system(despawn_overlay)
+                    .with_system(rewind::resume_from_rewind),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::GameOver)
+                    .with_system(replay::save_replay)
+                    .with_system(record_high_score)
+                    .with_system(spawn_game_over_overlay.after(record_high_score)),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::GameOver).with_system(handle_game_over_input),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::GameOver)
+                    .with_system(despawn_overlay)
+                    .with_system(despawn_game),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::LevelSelect)
+                    .with_system(campaign::spawn_level_select_overlay),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::LevelSelect)
+                    .with_system(campaign::handle_level_select_input),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::LevelSelect).with_system(despawn_overlay))
+            .add_system_set(
+                SystemSet::on_enter(GameState::LevelComplete)
+                    .with_system(replay::save_replay)
+                    .with_system(record_high_score)
+                    .with_system(campaign::complete_level),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::LevelComplete)
+                    .with_system(campaign::handle_level_complete_input),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::LevelComplete)
+                    .with_system(despawn_overlay)
+                    .with_system(despawn_game),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Replay)
+                    .with_system(replay::start_playback)
+                    .with_system(spawn_level.after(replay::start_playback))
+                    .with_system(spawn_hud)
+                    .with_system(sync_snakes.after(replay::start_playback))
+                    .with_system(sync_food.after(replay::start_playback))
+                    .with_system(sync_power_ups.after(replay::start_playback))
+                    .with_system(sync_food_countdowns.after(replay::start_playback)),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Replay)
+                    .with_system(replay::handle_playback_input)
+                    .with_system(replay::advance_playback.after(replay::handle_playback_input))
+                    .with_system(sync_snakes.after(replay::advance_playback))
+                    .with_system(sync_food.after(replay::advance_playback))
+                    .with_system(sync_power_ups.after(replay::advance_playback))
+                    .with_system(sync_food_countdowns.after(replay::advance_playback))
+                    .with_system(replay::update_playback_hud.after(replay::advance_playback)),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Replay).with_system(despawn_game))
+            .add_system_set(
+                SystemSet::on_enter(GameState::Controls).with_system(input::spawn_controls_overlay),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Controls).with_system(input::handle_controls_input),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Controls).with_system(despawn_overlay))
+            .add_system_set_to_stage(
+                CoreStage::PostUpdate,
+                SystemSet::new()
+                    .with_system(sync_score)
+                    .with_system(send_snake_events)
+                    .with_system(scale_arena)
+                    .with_system(translate_position)
+                    .with_system(scale_size),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(score: u32) -> HighScoreEntry {
+        HighScoreEntry {
+            player: "P1".to_string(),
+            score,
+            length: 1,
+            duration: 0.,
+            seed: None,
+        }
+    }
+
+    fn scores(high_scores: &HighScores, variant: &HighScoreVariant) -> Vec<u32> {
+        high_scores
+            .entries(variant)
+            .iter()
+            .map(|entry| entry.score)
+            .collect()
+    }
+
+    #[test]
+    fn high_scores_are_ranked_best_first() {
+        let variant =
+            HighScoreVariant::current(&GameConfig::default(), &Level::parse("a", ">").unwrap());
+        let mut high_scores = HighScores::default();
+        assert_eq!(high_scores.insert(variant.clone(), entry(20)), Some(0));
+        assert_eq!(high_scores.insert(variant.clone(), entry(40)), Some(0));
+        assert_eq!(high_scores.insert(variant.clone(), entry(30)), Some(1));
+        // Ties rank after the scores already in the table.
+        assert_eq!(high_scores.insert(variant.clone(), entry(30)), Some(2));
+        assert_eq!(scores(&high_scores, &variant), [40, 30, 30, 20]);
+    }
+
+    #[test]
+    fn high_score_tables_keep_only_the_best() {
+        let variant =
+            HighScoreVariant::current(&GameConfig::default(), &Level::parse("a", ">").unwrap());
+        let mut high_scores = HighScores::default();
+        for score in 1..=HIGH_SCORE_TABLE_SIZE as u32 {
+            high_scores.insert(variant.clone(), entry(score * 10));
+        }
+        assert_eq!(high_scores.insert(variant.clone(), entry(5)), None);
+        assert_eq!(
+            high_scores.insert(variant.clone(), entry(15)),
+            Some(HIGH_SCORE_TABLE_SIZE - 1)
+        );
+        let kept = scores(&high_scores, &variant);
+        assert_eq!(kept.len(), HIGH_SCORE_TABLE_SIZE);
+        assert_eq!(kept.last(), Some(&15));
+    }
+
+    #[test]
+    fn high_scores_are_kept_apart_per_variant() {
+        let config = GameConfig::default();
+        let open = HighScoreVariant::current(&config, &Level::parse("open", ">").unwrap());
+        let walled = HighScoreVariant::current(&config, &Level::parse("walled", ">#").unwrap());
+        let mut high_scores = HighScores::default();
+        high_scores.insert(open.clone(), entry(10));
+        assert_eq!(high_scores.insert(walled.clone(), entry(5)), Some(0));
+        assert_eq!(scores(&high_scores, &open), [10]);
+        assert_eq!(scores(&high_scores, &walled), [5]);
+    }
+
+    /// Ticks due over a second of frames lasting 1/64 s each.
+    fn ticks_in_a_second(tick_rate: &mut TickRate, simulation: &Simulation) -> usize {
+        let mut ticks = 0;
+        for _ in 0..64 {
+            while tick_rate.poll(simulation, 1. / 64.) == ShouldRun::YesAndCheckAgain {
+                ticks += 1;
+            }
+        }
+        ticks
+    }
+
+    #[test]
+    fn shorter_steps_tick_more_often() {
+        let config = GameConfig {
+            speed_ramp: None,
+            ..GameConfig::default()
+        };
+        let simulation = Simulation::new(&config, &Level::open(&config), &[], 0);
+        let mut tick_rate = TickRate::new(0.125);
+        assert_eq!(ticks_in_a_second(&mut tick_rate, &simulation), 8);
+        tick_rate.base_step = 0.0625;
+        assert_eq!(ticks_in_a_second(&mut tick_rate, &simulation), 16);
+    }
+}
@@ -1,320 +1,350 @@
-use bevy::core::FixedTimestep;
+use bevy::app::AppExit;
 use bevy::prelude::*;
+use clap::Parser;
+use snake::ai::Strategy;
+use snake::config::{Boundary, ControlScheme, Difficulty, FoodPolicy, GameConfig};
+use snake::level::Level;
+use snake::simulation::Simulation;
+use snake::{advance_simulation, GameState, SimulationPlugin, TickRate};
+use std::path::PathBuf;
+use std::process;
 
-const ARENA_SIZE: u32 = 25;
+#[cfg(feature = "render")]
+use {
+    snake::campaign::Campaign,
+    snake::replay::{Playback, Replay},
+    snake::{Player, SnakePlugin},
+    std::path::Path,
+};
 
-const SNAKE_HEAD_SIZE: f32 = 0.8;
-const SNAKE_HEAD_COLOR: Color = Color::rgb(0.8, 0.8, 0.8);
+#[cfg(feature = "render")]
+const DEFAULT_CAMPAIGN: &str = "assets/campaign.ron";
+const DEFAULT_MAX_TICKS: u64 = 100_000;
 
-const SNAKE_SEGMENT_SIZE: f32 = 0.5;
-const SNAKE_SEGMENT_COLOR: Color = Color::rgb(0.6, 0.6, 0.6);
+/// Ticks after which a headless round is called off.
+struct MaxTicks(u64);
 
-const FOOD_SIZE: f32 = 0.6;
-const FOOD_COLOR: Color = Color::rgb(0.2, 0.8, 0.2);
+#[derive(Parser)]
+#[clap(about = "A snake game")]
+struct Args {
+    /// Game configuration file (RON)
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// Level file drawn as a grid of tiles; sets the arena size
+    #[clap(long)]
+    level: Option<PathBuf>,
+    /// Campaign file listing levels and their goals
+    #[clap(long)]
+    campaign: Option<PathBuf>,
+    /// Preset of speed, arena, obstacles and food: easy, normal, hard or insane; the other
+    /// options override it
+    #[clap(long, parse(try_from_str = parse_difficulty))]
+    difficulty: Option<Difficulty>,
+    /// Number of tiles along the horizontal side of the arena
+    #[clap(long)]
+    arena_width: Option<u32>,
+    /// Number of tiles along the vertical side of the arena
+    #[clap(long)]
+    arena_height: Option<u32>,
+    /// What happens at the arena edges: wrap, walls, wrap-x or wrap-y
+    #[clap(long, parse(try_from_str = parse_boundary))]
+    boundary: Option<Boundary>,
+    /// Steering: absolute (one control per direction) or relative (rotate left/right)
+    #[clap(long, parse(try_from_str = parse_control_scheme))]
+    controls: Option<ControlScheme>,
+    /// Seconds between two snake moves
+    #[clap(long)]
+    move_step: Option<f64>,
+    /// Seconds between two food spawns
+    #[clap(long)]
+    food_step: Option<f64>,
+    /// When food appears: constant:N keeps N on the board, timed:N drops one every food step
+    /// up to N
+    #[clap(long, parse(try_from_str = parse_food_policy))]
+    food_policy: Option<FoodPolicy>,
+    /// Seconds before uneaten food disappears
+    #[clap(long)]
+    food_lifetime: Option<f64>,
+    /// Number of local players sharing the board, up to 4 snakes with the bots
+    #[clap(long)]
+    players: Option<usize>,
+    /// Comma-separated strategies of bot snakes: greedy, pathfinder or hamiltonian
+    #[clap(long, use_value_delimiter = true, parse(try_from_str = parse_strategy))]
+    bots: Option<Vec<Strategy>>,
+    /// Seed of the food placement and other randomness, to replay the same game
+    #[clap(long)]
+    seed: Option<u64>,
+    /// Name recorded in the high score table
+    #[clap(long)]
+    player: Option<String>,
+    /// Replay file to watch instead of playing
+    #[clap(long)]
+    replay: Option<PathBuf>,
+    /// Play a single round between bots as fast as possible without a window and print the
+    /// scores and seed; the round has no players' snakes
+    #[clap(long)]
+    headless: bool,
+    /// Ticks after which a headless round stops, in case the bots never die
+    #[clap(long, default_value_t = DEFAULT_MAX_TICKS)]
+    max_ticks: u64,
+    /// Keep the last ticks to step back and forth through while paused, for debugging
+    #[clap(long)]
+    debug_rewind: bool,
+}
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-enum Direction {
-    Up,
-    Right,
-    Down,
-    Left,
+fn parse_difficulty(value: &str) -> Result<Difficulty, String> {
+    Difficulty::ALL
+        .into_iter()
+        .find(|difficulty| difficulty.name() == value)
+        .ok_or_else(|| format!("unknown difficulty {:?}", value))
 }
 
-#[derive(Clone, Copy, Component)]
-struct Size {
-    width: f32,
-    height: f32,
+fn parse_boundary(value: &str) -> Result<Boundary, String> {
+    match value {
+        "wrap" => Ok(Boundary::Wrap),
+        "walls" => Ok(Boundary::Walls),
+        "wrap-x" => Ok(Boundary::Mixed {
+            wrap_x: true,
+            wrap_y: false,
+        }),
+        "wrap-y" => Ok(Boundary::Mixed {
+            wrap_x: false,
+            wrap_y: true,
+        }),
+        _ => Err(format!("unknown boundary {:?}", value)),
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Component)]
-struct Position {
-    x: i32,
-    y: i32,
+fn parse_control_scheme(value: &str) -> Result<ControlScheme, String> {
+    match value {
+        "absolute" => Ok(ControlScheme::Absolute),
+        "relative" => Ok(ControlScheme::Relative),
+        _ => Err(format!("unknown control scheme {:?}", value)),
+    }
 }
 
-impl Position {
-    fn do_move(&self, direction: Direction) -> Position {
-        match direction {
-            Direction::Up => Position {
-                x: self.x,
-                y: (self.y + 1).rem_euclid(ARENA_SIZE as i32),
-            },
-            Direction::Right => Position {
-                x: (self.x + 1).rem_euclid(ARENA_SIZE as i32),
-                y: self.y,
-            },
-            Direction::Down => Position {
-                x: self.x,
-                y: (self.y - 1).rem_euclid(ARENA_SIZE as i32),
-            },
-            Direction::Left => Position {
-                x: (self.x - 1).rem_euclid(ARENA_SIZE as i32),
-                y: self.y,
-            },
-        }
+fn parse_food_policy(value: &str) -> Result<FoodPolicy, String> {
+    let (policy, count) = value
+        .split_once(':')
+        .ok_or_else(|| format!("food policy {:?} lacks a count", value))?;
+    let count = count
+        .parse()
+        .map_err(|_| format!("invalid food count {:?}", count))?;
+    match policy {
+        "constant" => Ok(FoodPolicy::Constant(count)),
+        "timed" => Ok(FoodPolicy::Timed(count)),
+        _ => Err(format!("unknown food policy {:?}", policy)),
     }
 }
 
-#[derive(Component)]
-struct SnakeSegment {
-    next: Option<Entity>,
+fn parse_strategy(value: &str) -> Result<Strategy, String> {
+    match value {
+        "greedy" => Ok(Strategy::Greedy),
+        "pathfinder" => Ok(Strategy::Pathfinder),
+        "hamiltonian" => Ok(Strategy::Hamiltonian),
+        _ => Err(format!("unknown bot strategy {:?}", value)),
+    }
 }
 
-#[derive(Clone, Copy, Component)]
-struct SnakeHead {
-    direction: Direction,
-    next_direction: Direction,
+fn load_config(args: &Args) -> Result<GameConfig, String> {
+    let mut config = match &args.config {
+        Some(path) => GameConfig::load(path)
+            .map_err(|error| format!("cannot load config {:?}: {}", path, error))?,
+        None => match GameConfig::default_path() {
+            Some(path) if path.exists() => GameConfig::load(&path)
+                .map_err(|error| format!("cannot load config {:?}: {}", path, error))?,
+            _ => GameConfig::default(),
+        },
+    };
+    let overrides = &mut config.overrides;
+    if let Some(arena_width) = args.arena_width {
+        overrides.arena_width = Some(arena_width);
+    }
+    if let Some(arena_height) = args.arena_height {
+        overrides.arena_height = Some(arena_height);
+    }
+    if let Some(boundary) = args.boundary {
+        overrides.boundary = Some(boundary);
+    }
+    if let Some(move_step) = args.move_step {
+        overrides.move_step = Some(move_step);
+    }
+    if let Some(food_policy) = args.food_policy {
+        overrides.food_policy = Some(food_policy);
+    }
+    if let Some(food_lifetime) = args.food_lifetime {
+        overrides.food_lifetime = Some(Some(food_lifetime));
+    }
+    let difficulty = args.difficulty.unwrap_or(config.difficulty);
+    difficulty.apply(&mut config);
+    if let Some(control_scheme) = args.controls {
+        config.control_scheme = control_scheme;
+    }
+    if let Some(food_step) = args.food_step {
+        config.food_step = food_step;
+    }
+    if args.debug_rewind {
+        config.debug_rewind = true;
+    }
+    if let Some(seed) = args.seed {
+        config.seed = Some(seed);
+    }
+    if let Some(players) = args.players {
+        config.players = players;
+    }
+    if let Some(bots) = &args.bots {
+        config.bots = bots.clone();
+    }
+    if args.headless {
+        // Nobody steers the players' snakes without a window, so only bots take part.
+        if args.players.unwrap_or(0) > 0 {
+            return Err("headless rounds have no players, drop --players".to_string());
+        }
+        if config.bots.is_empty() {
+            return Err("headless rounds need at least one bot, see --bots".to_string());
+        }
+        config.players = 0;
+        // Nobody could step through a rewind either, and a round ending would wait for it.
+        config.debug_rewind = false;
+    }
+    config.validate()?;
+    Ok(config)
 }
 
-#[derive(Component)]
-struct Food;
+fn load_level(args: &Args, config: &mut GameConfig) -> Result<Option<Level>, String> {
+    let path = match &args.level {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let level =
+        Level::load(path).map_err(|error| format!("cannot load level {:?}: {}", path, error))?;
+    config.arena.width = level.width;
+    config.arena.height = level.height;
+    config.overrides.arena_width = Some(level.width);
+    config.overrides.arena_height = Some(level.height);
+    Ok(Some(level))
+}
 
-struct GrowEvent {
-    position: Position,
+#[cfg(feature = "render")]
+fn load_campaign(args: &Args) -> Result<Option<Campaign>, String> {
+    let path = match &args.campaign {
+        Some(path) => path.clone(),
+        None if Path::new(DEFAULT_CAMPAIGN).exists() => PathBuf::from(DEFAULT_CAMPAIGN),
+        None => return Ok(None),
+    };
+    Campaign::load(&path)
+        .map(Some)
+        .map_err(|error| format!("cannot load campaign {:?}: {}", path, error))
 }
 
+#[cfg(feature = "render")]
 fn setup_camera(mut commands: Commands) {
     commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    commands.spawn_bundle(UiCameraBundle::default());
 }
 
-fn translate_position(windows: Res<Windows>, mut query: Query<(&Position, &mut Transform)>) {
-    let window = windows.get_primary().unwrap();
-    let window_size = window.width();
-    let tile_size = window_size / ARENA_SIZE as f32;
-    for (position, mut transform) in query.iter_mut() {
-        transform.translation = Vec3::new(
-            -window_size / 2. + tile_size / 2. + position.x as f32 * tile_size,
-            -window_size / 2. + tile_size / 2. + position.y as f32 * tile_size,
-            0.,
-        );
+fn stop_at_max_ticks(
+    max_ticks: Res<MaxTicks>,
+    simulation: Res<Simulation>,
+    mut game_state: ResMut<State<GameState>>,
+) {
+    if simulation.ticks >= max_ticks.0 && !simulation.is_over() {
+        game_state.overwrite_set(GameState::GameOver).unwrap();
     }
 }
 
-fn scale_size(windows: Res<Windows>, mut query: Query<(&Size, &mut Transform)>) {
-    let window = windows.get_primary().unwrap();
-    let window_size = window.width();
-    let tile_size = window_size / ARENA_SIZE as f32;
-    for (size, mut transform) in query.iter_mut() {
-        transform.scale = Vec3::new(size.width * tile_size, size.height * tile_size, 1.);
+fn report_scores(
+    max_ticks: Res<MaxTicks>,
+    simulation: Res<Simulation>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    if simulation.board_full {
+        println!("board full");
+    } else if !simulation.is_over() && simulation.ticks >= max_ticks.0 {
+        println!("stopped after {} ticks", simulation.ticks);
     }
+    println!("{}", simulation.describe_scores());
+    println!("seed {}", simulation.seed);
+    app_exit_events.send(AppExit);
 }
 
-fn spawn_snake(mut commands: Commands) {
-    let snake_tail1 = commands
-        .spawn_bundle(SpriteBundle {
-            sprite: Sprite {
-                color: SNAKE_SEGMENT_COLOR,
-                ..default()
-            },
-            ..default()
-        })
-        .insert(Position { x: 14, y: 12 })
-        .insert(Size {
-            width: SNAKE_SEGMENT_SIZE,
-            height: SNAKE_SEGMENT_SIZE,
-        })
-        .insert(SnakeSegment { next: None })
-        .id();
-    let snake_tail2 = commands
-        .spawn_bundle(SpriteBundle {
-            sprite: Sprite {
-                color: SNAKE_SEGMENT_COLOR,
-                ..default()
-            },
-            ..default()
-        })
-        .insert(Position { x: 13, y: 12 })
-        .insert(Size {
-            width: SNAKE_SEGMENT_SIZE,
-            height: SNAKE_SEGMENT_SIZE,
-        })
-        .insert(SnakeSegment {
-            next: Some(snake_tail1),
-        })
-        .id();
-    commands
-        .spawn_bundle(SpriteBundle {
-            sprite: Sprite {
-                color: SNAKE_HEAD_COLOR,
-                ..default()
-            },
-            ..default()
-        })
-        .insert(Position { x: 12, y: 12 })
-        .insert(Size {
-            width: SNAKE_HEAD_SIZE,
-            height: SNAKE_HEAD_SIZE,
-        })
-        .insert(SnakeHead {
-            direction: Direction::Left,
-            next_direction: Direction::Left,
+fn main() {
+    let args = Args::parse();
+    let mut config = load_config(&args).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(1);
+    });
+    let level = load_level(&args, &mut config).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(1);
+    });
+    if args.headless {
+        let mut app = App::new();
+        app.insert_resource(TickRate {
+            unthrottled: true,
+            ..TickRate::new(config.move_step)
         })
-        .insert(SnakeSegment {
-            next: Some(snake_tail2),
-        });
-}
-
-fn handle_input(keyboard_input: Res<Input<KeyCode>>, mut snake_head_query: Query<&mut SnakeHead>) {
-    let mut snake_head = snake_head_query.single_mut();
-    if keyboard_input.pressed(KeyCode::Up) && snake_head.direction != Direction::Down {
-        snake_head.next_direction = Direction::Up;
-    } else if keyboard_input.pressed(KeyCode::Right) && snake_head.direction != Direction::Left {
-        snake_head.next_direction = Direction::Right;
-    } else if keyboard_input.pressed(KeyCode::Down) && snake_head.direction != Direction::Up {
-        snake_head.next_direction = Direction::Down;
-    } else if keyboard_input.pressed(KeyCode::Left) && snake_head.direction != Direction::Right {
-        snake_head.next_direction = Direction::Left;
+        .insert_resource(config);
+        if let Some(level) = level {
+            app.insert_resource(level);
+        }
+        app.insert_resource(MaxTicks(args.max_ticks))
+            .add_plugins(MinimalPlugins)
+            .add_plugin(SimulationPlugin)
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(stop_at_max_ticks.after(advance_simulation)),
+            )
+            .add_system_set(SystemSet::on_enter(GameState::GameOver).with_system(report_scores))
+            .run();
+        return;
     }
-}
-
-fn move_snake(
-    mut query_set: ParamSet<(
-        Query<(Entity, &mut SnakeHead, &Position)>,
-        Query<(&SnakeSegment, &mut Position)>,
-    )>,
-) {
-    let mut snake_head_query = query_set.p0();
-    let (mut snake_segment_entity, mut snake_head, snake_head_position) =
-        snake_head_query.single_mut();
-    snake_head.direction = snake_head.next_direction;
-    let mut next_position = snake_head_position.do_move(snake_head.direction);
 
-    let mut snake_segment_query = query_set.p1();
-    loop {
-        if let Ok((snake_segment, mut snake_segment_position)) =
-            snake_segment_query.get_mut(snake_segment_entity)
-        {
-            let next_next_position = *snake_segment_position;
-            snake_segment_position.x = next_position.x;
-            snake_segment_position.y = next_position.y;
-            next_position = next_next_position;
-            if let Some(next_entity) = snake_segment.next {
-                snake_segment_entity = next_entity;
-            } else {
-                break;
-            }
-        }
+    #[cfg(feature = "render")]
+    run_windowed(args, config, level);
+    #[cfg(not(feature = "render"))]
+    {
+        eprintln!("built without the render feature, only --headless rounds can run");
+        process::exit(1);
     }
 }
 
-fn spawn_food(
-    mut commands: Commands,
-    food_query: Query<&Position, With<Food>>,
-    snake_segment_query: Query<&Position, With<SnakeSegment>>,
-) {
-    let food_position = loop {
-        let food_position = Position {
-            x: (rand::random::<f32>() * ARENA_SIZE as f32).floor() as i32,
-            y: (rand::random::<f32>() * ARENA_SIZE as f32).floor() as i32,
-        };
-        if food_query
-            .iter()
-            .chain(snake_segment_query.iter())
-            .all(|position| food_position != *position)
-        {
-            break food_position;
-        }
-    };
-    commands
-        .spawn_bundle(SpriteBundle {
-            sprite: Sprite {
-                color: FOOD_COLOR,
-                ..default()
-            },
-            ..default()
-        })
-        .insert(food_position)
-        .insert(Size {
-            width: FOOD_SIZE,
-            height: FOOD_SIZE,
+#[cfg(feature = "render")]
+fn run_windowed(args: Args, config: GameConfig, level: Option<Level>) {
+    let campaign = load_campaign(&args).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(1);
+    });
+    let replay = args.replay.as_ref().map(|path| {
+        Replay::load(path).unwrap_or_else(|error| {
+            eprintln!("cannot load replay {:?}: {}", path, error);
+            process::exit(1);
         })
-        .insert(Food);
-}
+    });
 
-fn eat_food(
-    mut commands: Commands,
-    food_query: Query<(Entity, &Position), With<Food>>,
-    snake_head_query: Query<&Position, With<SnakeHead>>,
-    snake_segment_query: Query<(&Position, &SnakeSegment)>,
-    mut grow_event_writer: EventWriter<GrowEvent>,
-) {
-    let snake_head = snake_head_query.single();
-    for (entity, food_position) in food_query.iter() {
-        if *food_position == *snake_head {
-            commands.entity(entity).despawn();
-            grow_event_writer.send(GrowEvent {
-                position: *snake_segment_query
-                    .iter()
-                    .find(|(_, snake_segment)| snake_segment.next.is_none())
-                    .unwrap()
-                    .0,
-            });
-        }
+    let mut app = App::new();
+    app.insert_resource(WindowDescriptor {
+        title: "Snake".to_string(),
+        width: config.window_width,
+        height: config.window_height,
+        ..default()
+    })
+    .insert_resource(ClearColor(Color::rgb(0.02, 0.02, 0.02)))
+    .insert_resource(config);
+    if let Some(level) = level {
+        app.insert_resource(level);
     }
-}
-
-fn grow_snake(
-    mut commands: Commands,
-    mut snake_segment_query: Query<(&Position, &mut SnakeSegment)>,
-    mut event_reader: EventReader<GrowEvent>,
-) {
-    for grow_event in event_reader.iter() {
-        snake_segment_query
-            .iter_mut()
-            .find(|(_, snake_segment)| snake_segment.next.is_none())
-            .unwrap()
-            .1
-            .next = Some(
-            commands
-                .spawn_bundle(SpriteBundle {
-                    sprite: Sprite {
-                        color: SNAKE_SEGMENT_COLOR,
-                        ..default()
-                    },
-                    ..default()
-                })
-                .insert(grow_event.position)
-                .insert(Size {
-                    width: SNAKE_SEGMENT_SIZE,
-                    height: SNAKE_SEGMENT_SIZE,
-                })
-                .insert(SnakeSegment { next: None })
-                .id(),
-        );
+    if let Some(campaign) = campaign {
+        app.insert_resource(campaign);
     }
-}
-
-fn main() {
-    App::new()
-        .insert_resource(WindowDescriptor {
-            title: "Snake".to_string(),
-            width: 600.,
-            height: 600.,
-            ..default()
-        })
-        .insert_resource(ClearColor(Color::rgb(0.04, 0.04, 0.04)))
-        .add_event::<GrowEvent>()
-        .add_plugins(DefaultPlugins)
+    if let Some(replay) = replay {
+        app.insert_resource(Playback::new(replay));
+    }
+    if let Some(name) = args.player {
+        app.insert_resource(Player { name });
+    }
+    app.add_plugins(DefaultPlugins)
+        .add_plugin(SnakePlugin)
         .add_startup_system(setup_camera)
-        .add_startup_system(spawn_snake)
-        .add_system(handle_input)
-        .add_system(grow_snake.after(handle_input))
-        .add_system_set(
-            SystemSet::new()
-                .with_run_criteria(FixedTimestep::step(0.08))
-                .with_system(move_snake.after(grow_snake)),
-        )
-        .add_system(eat_food.after(move_snake))
-        .add_system_set(
-            SystemSet::new()
-                .with_run_criteria(FixedTimestep::step(3.0))
-                .with_system(spawn_food.after(move_snake)),
-        )
-        .add_system_set_to_stage(
-            CoreStage::PostUpdate,
-            SystemSet::new()
-                .with_system(translate_position)
-                .with_system(scale_size),
-        )
         .run();
 }
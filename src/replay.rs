@@ -0,0 +1,326 @@
+use crate::config::GameConfig;
+use crate::input::{Action, ActionInput};
+use crate::level::Level;
+use crate::simulation::{Simulation, Turn};
+#[cfg(feature = "render")]
+use crate::{input::Bindings, Hud};
+use crate::{read_ron, save_ron, GameState};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const REPLAY_DIRECTORY: &str = "snake/replays";
+const MIN_SPEED: f32 = 0.25;
+const MAX_SPEED: f32 = 8.;
+const SEEK_SECONDS: f64 = 5.;
+/// Replays kept on disk, the oldest being removed first.
+const MAX_SAVED_REPLAYS: usize = 50;
+
+/// Everything needed to reproduce a round: its setup and the turns handed to each tick.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub config: GameConfig,
+    pub level: Level,
+    pub carried_points: Vec<u32>,
+    /// Turns paired with the tick they were applied on, in order.
+    pub turns: Vec<(u64, Turn)>,
+    pub ticks: u64,
+}
+
+impl Replay {
+    pub fn new(config: &GameConfig, level: &Level, carried_points: &[u32], seed: u64) -> Replay {
+        Replay {
+            seed,
+            config: config.clone(),
+            level: level.clone(),
+            carried_points: carried_points.to_vec(),
+            turns: Vec::new(),
+            ticks: 0,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let replay: Replay = read_ron(path)?;
+        replay.config.validate_rules()?;
+        if (replay.level.width, replay.level.height)
+            != (replay.config.arena.width, replay.config.arena.height)
+        {
+            return Err("the level does not fit the arena".to_string());
+        }
+        Ok(replay)
+    }
+
+    pub fn directory() -> Option<PathBuf> {
+        dirs::data_dir().map(|data_dir| data_dir.join(REPLAY_DIRECTORY))
+    }
+
+    /// Saves the replay under a name made of the level, seed and current time.
+    pub fn save(&self) {
+        let directory = match Replay::directory() {
+            Some(directory) => directory,
+            None => return,
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let path = directory.join(format!(
+            "{}-{}-{}.ron",
+            self.level.name, self.seed, timestamp
+        ));
+        save_ron(&path, self, "replay");
+        if let Err(error) = Replay::prune(&directory) {
+            warn!(
+                "failed to remove old replays from {:?}: {}",
+                directory, error
+            );
+        }
+    }
+
+    /// Removes the oldest replays beyond the number kept.
+    fn prune(directory: &Path) -> Result<(), String> {
+        let mut replays = Vec::new();
+        for entry in fs::read_dir(directory).map_err(|error| error.to_string())? {
+            let path = entry.map_err(|error| error.to_string())?.path();
+            if path.extension().is_some_and(|extension| extension == "ron") {
+                let modified = fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .map_err(|error| error.to_string())?;
+                replays.push((modified, path));
+            }
+        }
+        replays.sort();
+        let excess = replays.len().saturating_sub(MAX_SAVED_REPLAYS);
+        for (_, path) in replays.into_iter().take(excess) {
+            fs::remove_file(&path).map_err(|error| error.to_string())?;
+        }
+        Ok(())
+    }
+
+    pub fn record(&mut self, tick: u64, turns: &[Turn]) {
+        self.turns.extend(turns.iter().map(|turn| (tick, *turn)));
+        self.ticks = tick;
+    }
+
+    /// Forgets everything recorded after a tick, to go on from it.
+    pub fn truncate(&mut self, tick: u64) {
+        let end = self
+            .turns
+            .partition_point(|(turn_tick, _)| *turn_tick <= tick);
+        self.turns.truncate(end);
+        self.ticks = tick;
+    }
+
+    pub fn start(&self) -> Simulation {
+        Simulation::new(&self.config, &self.level, &self.carried_points, self.seed)
+    }
+
+    pub fn turns_at(&self, tick: u64) -> Vec<Turn> {
+        let first = self
+            .turns
+            .partition_point(|(turn_tick, _)| *turn_tick < tick);
+        self.turns[first..]
+            .iter()
+            .take_while(|(turn_tick, _)| *turn_tick == tick)
+            .map(|(_, turn)| *turn)
+            .collect()
+    }
+
+    pub fn step(&self, simulation: &mut Simulation) {
+        let turns = self.turns_at(simulation.ticks + 1);
+        simulation.tick(&turns);
+    }
+
+    /// Brings the simulation to a tick, starting over when seeking backwards.
+    pub fn seek(&self, simulation: &mut Simulation, tick: u64) {
+        let tick = tick.min(self.ticks);
+        if tick < simulation.ticks {
+            *simulation = self.start();
+        }
+        while simulation.ticks < tick {
+            self.step(simulation);
+        }
+    }
+}
+
+/// The round being played, recorded as it goes.
+pub struct Recording {
+    pub replay: Replay,
+}
+
+#[derive(Default)]
+pub struct Playback {
+    pub replay: Option<Replay>,
+    pub paused: bool,
+    pub speed: f32,
+    /// Game time accumulated towards the next tick.
+    pub elapsed: f64,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        Playback {
+            replay: Some(replay),
+            ..default()
+        }
+    }
+}
+
+pub fn save_replay(recording: Res<Recording>) {
+    recording.replay.save();
+}
+
+/// Starts watching a replay given on the command line instead of showing the menu.
+pub fn watch_startup_replay(playback: Res<Playback>, mut game_state: ResMut<State<GameState>>) {
+    if playback.replay.is_some() {
+        game_state.overwrite_set(GameState::Replay).unwrap();
+    }
+}
+
+pub fn start_playback(mut playback: ResMut<Playback>, mut simulation: ResMut<Simulation>) {
+    playback.paused = false;
+    playback.speed = 1.;
+    playback.elapsed = 0.;
+    if let Some(replay) = &playback.replay {
+        *simulation = replay.start();
+    }
+}
+
+pub fn advance_playback(
+    time: Res<Time>,
+    mut playback: ResMut<Playback>,
+    mut simulation: ResMut<Simulation>,
+) {
+    let playback = &mut *playback;
+    let replay = match &playback.replay {
+        Some(replay) if !playback.paused => replay,
+        _ => return,
+    };
+    let move_step = replay.config.move_step;
+    playback.elapsed += time.delta_seconds_f64() * playback.speed as f64;
+    while playback.elapsed >= move_step / simulation.tick_rate_factor()
+        && simulation.ticks < replay.ticks
+    {
+        playback.elapsed -= move_step / simulation.tick_rate_factor();
+        replay.step(&mut simulation);
+    }
+    if simulation.ticks >= replay.ticks {
+        playback.elapsed = 0.;
+    }
+}
+
+pub fn handle_playback_input(
+    mut actions: ResMut<ActionInput>,
+    mut game_state: ResMut<State<GameState>>,
+    mut playback: ResMut<Playback>,
+    mut simulation: ResMut<Simulation>,
+) {
+    if actions.just_pressed(Action::Confirm) {
+        actions.consume(Action::Confirm);
+        playback.paused = !playback.paused;
+    } else if actions.just_pressed(Action::TurnUp) {
+        playback.speed = (playback.speed * 2.).min(MAX_SPEED);
+    } else if actions.just_pressed(Action::TurnDown) {
+        playback.speed = (playback.speed / 2.).max(MIN_SPEED);
+    } else if actions.just_pressed(Action::TurnLeft) || actions.just_pressed(Action::TurnRight) {
+        if let Some(replay) = &playback.replay {
+            let seek_ticks = (SEEK_SECONDS / replay.config.move_step).round() as u64;
+            let tick = if actions.just_pressed(Action::TurnLeft) {
+                simulation.ticks.saturating_sub(seek_ticks)
+            } else {
+                simulation.ticks + seek_ticks
+            };
+            replay.seek(&mut simulation, tick);
+        }
+        playback.elapsed = 0.;
+    } else if actions.just_pressed(Action::Back) {
+        actions.consume(Action::Back);
+        game_state.set(GameState::Menu).unwrap();
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn update_playback_hud(
+    bindings: Res<Bindings>,
+    playback: Res<Playback>,
+    simulation: Res<Simulation>,
+    mut hud_query: Query<&mut Text, With<Hud>>,
+) {
+    let ticks = playback.replay.as_ref().map_or(0, |replay| replay.ticks);
+    let mut text = hud_query.single_mut();
+    text.sections[0].value = format!(
+        "REPLAY   {}   tick {}/{}   {}x{}\n{} pause   {}/{} speed   {}/{} seek   {} menu",
+        simulation.describe_scores(),
+        simulation.ticks,
+        ticks,
+        playback.speed,
+        if playback.paused { "   paused" } else { "" },
+        bindings.describe(Action::Confirm),
+        bindings.describe(Action::TurnUp),
+        bindings.describe(Action::TurnDown),
+        bindings.describe(Action::TurnLeft),
+        bindings.describe(Action::TurnRight),
+        bindings.describe(Action::Back),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Steer;
+
+    fn assert_same(replayed: &Simulation, simulation: &Simulation) {
+        assert_eq!(replayed.ticks, simulation.ticks);
+        for (replayed, snake) in replayed.snakes.iter().zip(&simulation.snakes) {
+            assert_eq!(replayed.body, snake.body);
+            assert_eq!(replayed.points, snake.points);
+            assert_eq!(replayed.alive, snake.alive);
+        }
+        let food = |simulation: &Simulation| {
+            simulation
+                .food
+                .iter()
+                .map(|food| (food.position, food.kind))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(food(replayed), food(simulation));
+    }
+
+    #[test]
+    fn seeking_reproduces_the_recorded_round() {
+        let config = GameConfig::default();
+        let level = Level::open(&config);
+        let mut simulation = Simulation::new(&config, &level, &[], 7);
+        let mut replay = Replay::new(&config, &level, &[], 7);
+        let mut halfway = None;
+        while simulation.ticks < 100 && !simulation.is_over() {
+            let turns = match simulation.ticks % 8 {
+                0 => vec![Turn {
+                    snake: 0,
+                    steer: Steer::Clockwise,
+                }],
+                4 => vec![Turn {
+                    snake: 0,
+                    steer: Steer::CounterClockwise,
+                }],
+                _ => Vec::new(),
+            };
+            simulation.tick(&turns);
+            replay.record(simulation.ticks, &turns);
+            if simulation.ticks == 50 {
+                halfway = Some(simulation.clone());
+            }
+        }
+
+        let mut replayed = replay.start();
+        replay.seek(&mut replayed, replay.ticks);
+        assert_same(&replayed, &simulation);
+
+        // Seeking backwards starts the round over.
+        let halfway = halfway.unwrap();
+        replay.seek(&mut replayed, halfway.ticks);
+        assert_same(&replayed, &halfway);
+    }
+}
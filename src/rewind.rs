@@ -0,0 +1,253 @@
+use crate::config::GameConfig;
+use crate::input::{Action, Bindings};
+use crate::replay::Recording;
+use crate::simulation::{PendingTurns, Simulation};
+use crate::RunTime;
+#[cfg(feature = "render")]
+use crate::{input::ActionInput, Overlay};
+use bevy::prelude::*;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// The board after a tick, with the round time it was reached at.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub simulation: Simulation,
+    pub elapsed: Duration,
+}
+
+/// Snapshots of the last ticks, to step back and forth through while paused, including after
+/// the tick that ends a round. Only kept when the config turns on `debug_rewind`.
+#[derive(Default)]
+pub struct Rewind {
+    pub snapshots: VecDeque<Snapshot>,
+    pub capacity: usize,
+    /// Index of the snapshot on the board.
+    pub cursor: usize,
+}
+
+impl Rewind {
+    pub fn reset(&mut self, capacity: usize, snapshot: Snapshot) {
+        self.capacity = capacity.max(1);
+        self.snapshots.clear();
+        self.snapshots.push_back(snapshot);
+        self.cursor = 0;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 1
+    }
+
+    pub fn push(&mut self, snapshot: Snapshot) {
+        self.snapshots.push_back(snapshot);
+        while self.snapshots.len() > self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.cursor = self.snapshots.len() - 1;
+    }
+
+    pub fn step_back(&mut self) -> Option<&Snapshot> {
+        if self.cursor == 0 {
+            return None;
+        }
+        self.cursor -= 1;
+        self.snapshots.get(self.cursor)
+    }
+
+    pub fn step_forward(&mut self) -> Option<&Snapshot> {
+        if self.cursor + 1 >= self.snapshots.len() {
+            return None;
+        }
+        self.cursor += 1;
+        self.snapshots.get(self.cursor)
+    }
+
+    /// Number of ticks the board is behind the latest snapshot.
+    pub fn rewound(&self) -> usize {
+        self.snapshots.len().saturating_sub(self.cursor + 1)
+    }
+
+    /// Forgets the snapshots after the one on the board, so that play continues from it.
+    pub fn truncate(&mut self) {
+        self.snapshots.truncate(self.cursor + 1);
+    }
+}
+
+pub fn pause_text(bindings: &Bindings, rewind: &Rewind, simulation: &Simulation) -> String {
+    let mut text = if simulation.is_over() {
+        format!(
+            "ROUND OVER\n\npress {} for the results",
+            bindings.describe(Action::Pause)
+        )
+    } else {
+        format!(
+            "PAUSED\n\npress {} to resume",
+            bindings.describe(Action::Pause)
+        )
+    };
+    if rewind.is_enabled() {
+        text += &format!(
+            "\n{}/{} to step through the last {} ticks",
+            bindings.describe(Action::StepBack),
+            bindings.describe(Action::StepForward),
+            rewind.snapshots.len().saturating_sub(1),
+        );
+    }
+    if rewind.rewound() > 0 {
+        text += &format!("\n\ntick {} ({} back)", simulation.ticks, rewind.rewound());
+    }
+    text
+}
+
+pub fn reset_rewind(
+    config: Res<GameConfig>,
+    simulation: Res<Simulation>,
+    run_time: Res<RunTime>,
+    mut rewind: ResMut<Rewind>,
+) {
+    let capacity = if config.debug_rewind {
+        config.rewind_ticks
+    } else {
+        1
+    };
+    rewind.reset(
+        capacity,
+        Snapshot {
+            simulation: simulation.clone(),
+            elapsed: run_time.stopwatch.elapsed(),
+        },
+    );
+}
+
+pub fn record_snapshot(
+    simulation: Res<Simulation>,
+    run_time: Res<RunTime>,
+    mut rewind: ResMut<Rewind>,
+) {
+    if rewind.is_enabled() {
+        rewind.push(Snapshot {
+            simulation: simulation.clone(),
+            elapsed: run_time.stopwatch.elapsed(),
+        });
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn handle_rewind_input(
+    actions: Res<ActionInput>,
+    bindings: Res<Bindings>,
+    mut rewind: ResMut<Rewind>,
+    mut simulation: ResMut<Simulation>,
+    mut run_time: ResMut<RunTime>,
+    mut overlay_query: Query<&mut Text, With<Overlay>>,
+) {
+    let snapshot = if actions.just_pressed(Action::StepBack) {
+        rewind.step_back()
+    } else if actions.just_pressed(Action::StepForward) {
+        rewind.step_forward()
+    } else {
+        None
+    };
+    if let Some(snapshot) = snapshot {
+        *simulation = snapshot.simulation.clone();
+        run_time.stopwatch.set_elapsed(snapshot.elapsed);
+        for mut text in overlay_query.iter_mut() {
+            text.sections[0].value = pause_text(&bindings, &rewind, &simulation);
+        }
+    }
+}
+
+/// Resumes from the snapshot on the board, dropping the ticks after it from the recording too.
+pub fn resume_from_rewind(
+    mut rewind: ResMut<Rewind>,
+    simulation: Res<Simulation>,
+    mut recording: ResMut<Recording>,
+    mut pending_turns: ResMut<PendingTurns>,
+) {
+    if rewind.rewound() == 0 {
+        return;
+    }
+    rewind.truncate();
+    recording.replay.truncate(simulation.ticks);
+    pending_turns.turns.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::Level;
+
+    /// Snapshots of a round at tick 0, 1, 2 and so on.
+    fn snapshots() -> impl Iterator<Item = Snapshot> {
+        let config = GameConfig::default();
+        let simulation = Simulation::new(&config, &Level::open(&config), &[], 0);
+        (0..).map(move |ticks| Snapshot {
+            simulation: Simulation {
+                ticks,
+                ..simulation.clone()
+            },
+            elapsed: Duration::ZERO,
+        })
+    }
+
+    fn ticks(snapshot: Option<&Snapshot>) -> Option<u64> {
+        snapshot.map(|snapshot| snapshot.simulation.ticks)
+    }
+
+    #[test]
+    fn push_drops_the_oldest_snapshot_at_capacity() {
+        let mut snapshots = snapshots();
+        let mut rewind = Rewind::default();
+        rewind.reset(3, snapshots.next().unwrap());
+        for snapshot in snapshots.take(4) {
+            rewind.push(snapshot);
+        }
+        let kept: Vec<u64> = rewind
+            .snapshots
+            .iter()
+            .map(|snapshot| snapshot.simulation.ticks)
+            .collect();
+        assert_eq!(kept, [2, 3, 4]);
+        assert_eq!(rewind.cursor, 2);
+        assert_eq!(rewind.rewound(), 0);
+    }
+
+    #[test]
+    fn stepping_stops_at_both_ends() {
+        let mut snapshots = snapshots();
+        let mut rewind = Rewind::default();
+        rewind.reset(3, snapshots.next().unwrap());
+        rewind.push(snapshots.next().unwrap());
+        rewind.push(snapshots.next().unwrap());
+
+        assert_eq!(ticks(rewind.step_forward()), None);
+        assert_eq!(ticks(rewind.step_back()), Some(1));
+        assert_eq!(rewind.rewound(), 1);
+        assert_eq!(ticks(rewind.step_back()), Some(0));
+        assert_eq!(ticks(rewind.step_back()), None);
+        assert_eq!(rewind.rewound(), 2);
+        assert_eq!(ticks(rewind.step_forward()), Some(1));
+        assert_eq!(ticks(rewind.step_forward()), Some(2));
+        assert_eq!(ticks(rewind.step_forward()), None);
+        assert_eq!(rewind.rewound(), 0);
+    }
+
+    #[test]
+    fn truncate_forgets_the_ticks_after_the_cursor() {
+        let mut snapshots = snapshots();
+        let mut rewind = Rewind::default();
+        rewind.reset(5, snapshots.next().unwrap());
+        for snapshot in snapshots.by_ref().take(3) {
+            rewind.push(snapshot);
+        }
+        rewind.step_back();
+        rewind.step_back();
+        rewind.truncate();
+        assert_eq!(rewind.snapshots.len(), 2);
+        assert_eq!(rewind.rewound(), 0);
+
+        rewind.push(snapshots.next().unwrap());
+        assert_eq!(ticks(rewind.snapshots.back()), Some(4));
+        assert_eq!(ticks(rewind.step_back()), Some(1));
+    }
+}
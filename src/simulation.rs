@@ -0,0 +1,1245 @@
+use crate::ai::SnakeController;
+use crate::config::{Arena, Boundary, FoodPolicy, GameConfig, RampMeasure, SpeedRamp};
+use crate::level::Level;
+use crate::{Direction, Position};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// How a player or bot asks a snake to turn.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Steer {
+    Toward(Direction),
+    Clockwise,
+    CounterClockwise,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Turn {
+    pub snake: usize,
+    pub steer: Steer,
+}
+
+/// Turns requested since the last tick, handed to the next one.
+#[derive(Default)]
+pub struct PendingTurns {
+    pub turns: Vec<Turn>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TickEvent {
+    Ate {
+        snake: usize,
+        position: Position,
+        kind: FoodKind,
+        points: u32,
+    },
+    Died {
+        snake: usize,
+    },
+    PoweredUp {
+        snake: usize,
+        position: Position,
+        kind: PowerUpKind,
+    },
+    PowerExpired {
+        snake: usize,
+        kind: PowerUpKind,
+    },
+    /// Snakes and walls cover every tile: the board is full and the round is won.
+    BoardFull,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum FoodKind {
+    Normal,
+    /// Worth more points and grows the snake by several segments.
+    Bonus,
+    /// Takes segments off the tail.
+    Shrink,
+    /// Raises the tick rate for a while.
+    SpeedUp,
+    /// Lowers the tick rate for a while.
+    SlowDown,
+    /// Kills the snake eating it.
+    Poison,
+}
+
+/// A temporary change of the tick rate.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SpeedEffect {
+    pub factor: f64,
+    /// Tick on which the tick rate goes back to normal.
+    pub until: u64,
+}
+
+/// Pickups giving the snake that takes them a status effect for a while.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum PowerUpKind {
+    /// Passes through its own body.
+    Ghost,
+    /// Passes through walls and the arena edges.
+    Invincible,
+    /// Pulls nearby food towards the head.
+    Magnet,
+    /// Multiplies the points of food eaten.
+    Multiplier,
+    /// Swaps a player's controls around; bots are not affected.
+    Reverse,
+}
+
+impl PowerUpKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PowerUpKind::Ghost => "ghost",
+            PowerUpKind::Invincible => "invincible",
+            PowerUpKind::Magnet => "magnet",
+            PowerUpKind::Multiplier => "multiplier",
+            PowerUpKind::Reverse => "reverse",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PowerUpItem {
+    pub position: Position,
+    pub kind: PowerUpKind,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StatusEffect {
+    pub kind: PowerUpKind,
+    /// Tick on which the effect wears off.
+    pub until: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FoodItem {
+    pub position: Position,
+    pub kind: FoodKind,
+    /// Tick on which the food disappears if still uneaten.
+    pub expires: Option<u64>,
+}
+
+/// Tiles holding nothing, kept up to date as the board changes so that food lands on one
+/// picked uniformly at random in constant time.
+#[derive(Clone, Default)]
+pub struct FreeCells {
+    cells: Vec<Position>,
+    indices: HashMap<Position, usize>,
+}
+
+impl FreeCells {
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn contains(&self, position: Position) -> bool {
+        self.indices.contains_key(&position)
+    }
+
+    pub fn insert(&mut self, position: Position) {
+        if !self.indices.contains_key(&position) {
+            self.indices.insert(position, self.cells.len());
+            self.cells.push(position);
+        }
+    }
+
+    pub fn remove(&mut self, position: Position) {
+        if let Some(index) = self.indices.remove(&position) {
+            self.cells.swap_remove(index);
+            if let Some(moved) = self.cells.get(index) {
+                self.indices.insert(*moved, index);
+            }
+        }
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng) -> Option<Position> {
+        if self.cells.is_empty() {
+            return None;
+        }
+        Some(self.cells[rng.gen_range(0..self.cells.len())])
+    }
+}
+
+#[derive(Clone)]
+pub struct Snake {
+    pub name: String,
+    pub controller: SnakeController,
+    /// Segment positions, head first.
+    pub body: VecDeque<Position>,
+    pub direction: Direction,
+    pub turns: VecDeque<Direction>,
+    /// Segments still to be added, one per move.
+    pub growth: u32,
+    pub points: u32,
+    pub eaten: u32,
+    pub alive: bool,
+    pub effects: Vec<StatusEffect>,
+}
+
+impl Snake {
+    pub fn head(&self) -> Position {
+        self.body[0]
+    }
+
+    pub fn length(&self) -> u32 {
+        self.body.len() as u32
+    }
+
+    pub fn is_bot(&self) -> bool {
+        matches!(self.controller, SnakeController::Bot(_))
+    }
+
+    pub fn has_effect(&self, kind: PowerUpKind) -> bool {
+        self.effects.iter().any(|effect| effect.kind == kind)
+    }
+
+    /// Starts an effect, or makes it last longer if already on.
+    pub fn give_effect(&mut self, kind: PowerUpKind, until: u64) {
+        match self.effects.iter_mut().find(|effect| effect.kind == kind) {
+            Some(effect) => effect.until = until,
+            None => self.effects.push(StatusEffect { kind, until }),
+        }
+    }
+
+    /// The direction the snake will be heading in once the already queued turns are taken.
+    pub fn heading(&self) -> Direction {
+        self.turns.back().copied().unwrap_or(self.direction)
+    }
+
+    /// Queues a turn unless the queue is full or the turn would go straight on or reverse
+    /// the heading.
+    pub fn queue_turn(&mut self, direction: Direction, capacity: usize) -> bool {
+        let heading = self.heading();
+        if self.turns.len() >= capacity || direction == heading || direction == heading.opposite() {
+            return false;
+        }
+        self.turns.push_back(direction);
+        true
+    }
+}
+
+/// The whole game board and its rules, free of any rendering so it can run headless.
+#[derive(Clone)]
+pub struct Simulation {
+    pub arena: Arena,
+    pub walls: Vec<Position>,
+    pub snakes: Vec<Snake>,
+    pub food: Vec<FoodItem>,
+    pub food_points: u32,
+    /// Spawn weights of each food kind, in the configured order.
+    pub food_weights: Vec<(FoodKind, u32)>,
+    pub bonus_points: u32,
+    pub bonus_growth: u32,
+    pub shrink_segments: u32,
+    pub speed_up_factor: f64,
+    pub slow_down_factor: f64,
+    pub speed_effect_ticks: u64,
+    pub speed_effect: Option<SpeedEffect>,
+    pub speed_ramp: Option<SpeedRamp>,
+    pub power_ups: Vec<PowerUpItem>,
+    pub power_up_weights: Vec<(PowerUpKind, u32)>,
+    /// Ticks between two power-up spawns.
+    pub power_up_interval: u64,
+    pub max_power_ups: usize,
+    /// Ticks a power-up's effect lasts.
+    pub power_up_ticks: u64,
+    pub magnet_range: u32,
+    pub score_multiplier: u32,
+    pub food_policy: FoodPolicy,
+    /// Ticks between two food spawns under a timed policy.
+    pub food_interval: u64,
+    /// Ticks food stays on the board uneaten, if limited.
+    pub food_lifetime: Option<u64>,
+    pub input_buffer_size: usize,
+    pub free: FreeCells,
+    /// Set once every tile is taken, which wins the round.
+    pub board_full: bool,
+    pub ticks: u64,
+    pub seed: u64,
+    /// Source of all randomness in the game, so that a seed and the turns taken on each tick
+    /// reproduce a round exactly.
+    pub rng: StdRng,
+}
+
+impl Simulation {
+    /// Sets up a round on a level; `carried_points` are the players' points from earlier
+    /// campaign stages.
+    pub fn new(
+        config: &GameConfig,
+        level: &Level,
+        carried_points: &[u32],
+        seed: u64,
+    ) -> Simulation {
+        let starts = level.snake_starts(config.snakes());
+        let snakes = (0..config.snakes())
+            .map(|index| {
+                let controller = config.controller(index);
+                let start = starts.get(index);
+                Snake {
+                    name: match controller {
+                        SnakeController::Player(player) => format!("P{}", player + 1),
+                        SnakeController::Bot(strategy) => strategy.name().to_string(),
+                    },
+                    controller,
+                    body: start
+                        .map_or_else(VecDeque::new, |start| start.body.iter().copied().collect()),
+                    direction: start.map_or(Direction::Right, |start| start.direction),
+                    turns: VecDeque::new(),
+                    growth: 0,
+                    points: carried_points.get(index).copied().unwrap_or(0),
+                    eaten: 0,
+                    alive: start.is_some(),
+                    effects: Vec::new(),
+                }
+            })
+            .collect();
+        let mut simulation = Simulation {
+            arena: config.arena,
+            walls: level.walls.clone(),
+            snakes,
+            food: level
+                .food
+                .iter()
+                .map(|position| FoodItem {
+                    position: *position,
+                    kind: FoodKind::Normal,
+                    expires: None,
+                })
+                .collect(),
+            food_points: config.food_points,
+            food_weights: config
+                .food_kinds
+                .iter()
+                .map(|food_kind| (food_kind.kind, food_kind.weight))
+                .collect(),
+            bonus_points: config.bonus_points,
+            bonus_growth: config.bonus_growth,
+            shrink_segments: config.shrink_segments,
+            speed_up_factor: config.speed_up_factor,
+            slow_down_factor: config.slow_down_factor,
+            speed_effect_ticks: ((config.speed_effect_duration / config.move_step).round() as u64)
+                .max(1),
+            speed_effect: None,
+            speed_ramp: config.speed_ramp.clone(),
+            power_ups: Vec::new(),
+            power_up_weights: config
+                .power_ups
+                .iter()
+                .map(|power_up| (power_up.kind, power_up.weight))
+                .collect(),
+            power_up_interval: ((config.power_up_step / config.move_step).round() as u64).max(1),
+            max_power_ups: config.max_power_ups,
+            power_up_ticks: ((config.power_up_duration / config.move_step).round() as u64).max(1),
+            magnet_range: config.magnet_range,
+            score_multiplier: config.score_multiplier,
+            food_policy: config.food_policy,
+            food_interval: ((config.food_step / config.move_step).round() as u64).max(1),
+            food_lifetime: config
+                .food_lifetime
+                .map(|lifetime| ((lifetime / config.move_step).round() as u64).max(1)),
+            input_buffer_size: config.input_buffer_size,
+            free: FreeCells::default(),
+            board_full: false,
+            ticks: 0,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        };
+        for y in 0..config.arena.height as i32 {
+            for x in 0..config.arena.width as i32 {
+                simulation.release(Position { x, y });
+            }
+        }
+        simulation.replenish_food();
+        simulation
+    }
+
+    pub fn queue(&mut self, turn: Turn) {
+        let capacity = self.input_buffer_size;
+        let snake = match self.snakes.get_mut(turn.snake) {
+            Some(snake) if snake.alive => snake,
+            _ => return,
+        };
+        let reversed = snake.has_effect(PowerUpKind::Reverse) && !snake.is_bot();
+        let direction = match (turn.steer, reversed) {
+            (Steer::Toward(direction), false) => direction,
+            (Steer::Toward(direction), true) => direction.opposite(),
+            (Steer::Clockwise, false) | (Steer::CounterClockwise, true) => {
+                snake.heading().clockwise()
+            }
+            (Steer::CounterClockwise, false) | (Steer::Clockwise, true) => {
+                snake.heading().counter_clockwise()
+            }
+        };
+        snake.queue_turn(direction, capacity);
+    }
+
+    /// Whether a position holds a wall, food, a power-up or a live snake.
+    pub fn is_occupied(&self, position: Position) -> bool {
+        self.walls.contains(&position)
+            || self.food.iter().any(|food| food.position == position)
+            || self
+                .power_ups
+                .iter()
+                .any(|power_up| power_up.position == position)
+            || self
+                .snakes
+                .iter()
+                .filter(|snake| snake.alive)
+                .any(|snake| snake.body.contains(&position))
+    }
+
+    /// Returns a tile to the free cells unless something still holds it.
+    fn release(&mut self, position: Position) {
+        if !self.is_occupied(position) {
+            self.free.insert(position);
+        }
+    }
+
+    /// Moves every live snake one tile, applying the given turns first, resolves deaths,
+    /// pickups and eating, then removes expired food and effects and drops new food and
+    /// power-ups.
+    pub fn tick(&mut self, turns: &[Turn]) -> Vec<TickEvent> {
+        for turn in turns {
+            self.queue(*turn);
+        }
+        self.ticks += 1;
+
+        let mut dying = Vec::new();
+        let mut moves = Vec::new();
+        for (index, snake) in self.snakes.iter_mut().enumerate() {
+            if !snake.alive {
+                continue;
+            }
+            if let Some(direction) = snake.turns.pop_front() {
+                snake.direction = direction;
+            }
+            let arena = if snake.has_effect(PowerUpKind::Invincible) {
+                Arena {
+                    boundary: Boundary::Wrap,
+                    ..self.arena
+                }
+            } else {
+                self.arena
+            };
+            match snake.head().do_move(snake.direction, arena) {
+                Some(position) => moves.push((index, snake.head(), position)),
+                None => dying.push(index),
+            }
+        }
+
+        // Heads swapping tiles pass through each other without ever sharing a tile, so catch
+        // that head-to-head collision here.
+        for (offset, (snake, position, next_position)) in moves.iter().enumerate() {
+            for (other_snake, other_position, other_next_position) in &moves[offset + 1..] {
+                if next_position == other_position && other_next_position == position {
+                    dying.push(*snake);
+                    dying.push(*other_snake);
+                }
+            }
+        }
+
+        let mut vacated = Vec::new();
+        for (index, _, position) in &moves {
+            let snake = &mut self.snakes[*index];
+            snake.body.push_front(*position);
+            if snake.growth > 0 {
+                snake.growth -= 1;
+            } else if let Some(tail) = snake.body.pop_back() {
+                vacated.push(tail);
+            }
+            self.free.remove(*position);
+        }
+        for position in vacated {
+            self.release(position);
+        }
+
+        // A head dies on walls and on any other segment, its own body and other heads
+        // included, unless an effect lets it through.
+        for (index, _, position) in &moves {
+            let ghost = self.snakes[*index].has_effect(PowerUpKind::Ghost);
+            let hit_snake = self.snakes.iter().enumerate().any(|(other, snake)| {
+                snake.alive
+                    && !(ghost && other == *index)
+                    && snake
+                        .body
+                        .iter()
+                        .skip(if other == *index { 1 } else { 0 })
+                        .any(|segment| segment == position)
+            });
+            let hit_wall = self.walls.contains(position)
+                && !self.snakes[*index].has_effect(PowerUpKind::Invincible);
+            if hit_snake || hit_wall {
+                dying.push(*index);
+            }
+        }
+
+        let mut events = Vec::new();
+        for (index, _, position) in &moves {
+            if dying.contains(index) {
+                continue;
+            }
+            if let Some(power_up) = self
+                .power_ups
+                .iter()
+                .position(|power_up| power_up.position == *position)
+            {
+                let power_up = self.power_ups.swap_remove(power_up);
+                self.snakes[*index].give_effect(power_up.kind, self.ticks + self.power_up_ticks);
+                events.push(TickEvent::PoweredUp {
+                    snake: *index,
+                    position: *position,
+                    kind: power_up.kind,
+                });
+            }
+        }
+
+        let mut shed = Vec::new();
+        for (index, _, position) in &moves {
+            if dying.contains(index) {
+                continue;
+            }
+            let food = match self.food.iter().position(|food| food.position == *position) {
+                Some(food) => self.food.swap_remove(food),
+                None => continue,
+            };
+            let points = match food.kind {
+                FoodKind::Bonus => self.bonus_points,
+                FoodKind::Poison => 0,
+                _ => self.food_points,
+            };
+            let snake = &mut self.snakes[*index];
+            let points = if snake.has_effect(PowerUpKind::Multiplier) {
+                points * self.score_multiplier
+            } else {
+                points
+            };
+            snake.points += points;
+            match food.kind {
+                FoodKind::Normal => snake.growth += 1,
+                FoodKind::Bonus => snake.growth += self.bonus_growth,
+                FoodKind::Shrink => {
+                    for _ in 0..self.shrink_segments {
+                        if snake.body.len() <= 1 {
+                            break;
+                        }
+                        shed.extend(snake.body.pop_back());
+                    }
+                }
+                FoodKind::SpeedUp | FoodKind::SlowDown => {
+                    snake.growth += 1;
+                    self.speed_effect = Some(SpeedEffect {
+                        factor: if food.kind == FoodKind::SpeedUp {
+                            self.speed_up_factor
+                        } else {
+                            self.slow_down_factor
+                        },
+                        until: self.ticks + self.speed_effect_ticks,
+                    });
+                }
+                FoodKind::Poison => dying.push(*index),
+            }
+            if food.kind != FoodKind::Poison {
+                snake.eaten += 1;
+            }
+            events.push(TickEvent::Ate {
+                snake: *index,
+                position: *position,
+                kind: food.kind,
+                points,
+            });
+        }
+        for position in shed {
+            self.release(position);
+        }
+
+        for index in dying {
+            let snake = &mut self.snakes[index];
+            if snake.alive {
+                snake.alive = false;
+                events.push(TickEvent::Died { snake: index });
+                let body: Vec<Position> = self.snakes[index].body.iter().copied().collect();
+                for position in body {
+                    self.release(position);
+                }
+            }
+        }
+
+        self.pull_food();
+
+        let ticks = self.ticks;
+        let (expired, food): (Vec<FoodItem>, Vec<FoodItem>) = self
+            .food
+            .drain(..)
+            .partition(|food| food.expires.is_some_and(|expires| expires <= ticks));
+        self.food = food;
+        for food in expired {
+            self.release(food.position);
+        }
+        self.replenish_food();
+        if matches!(self.speed_effect, Some(effect) if effect.until <= self.ticks) {
+            self.speed_effect = None;
+        }
+        for (index, snake) in self.snakes.iter_mut().enumerate() {
+            for effect in snake.effects.iter().filter(|effect| effect.until <= ticks) {
+                events.push(TickEvent::PowerExpired {
+                    snake: index,
+                    kind: effect.kind,
+                });
+            }
+            snake.effects.retain(|effect| effect.until > ticks);
+        }
+        if self.max_power_ups > 0
+            && self.ticks.is_multiple_of(self.power_up_interval)
+            && self.power_ups.len() < self.max_power_ups
+        {
+            self.spawn_power_up();
+        }
+        // Food and power-ups are still room to grow into, so only snakes and walls fill it.
+        if self.free.is_empty()
+            && self.food.is_empty()
+            && self.power_ups.is_empty()
+            && !self.board_full
+        {
+            self.board_full = true;
+            events.push(TickEvent::BoardFull);
+        }
+        events
+    }
+
+    fn replenish_food(&mut self) {
+        match self.food_policy {
+            FoodPolicy::Constant(count) => {
+                while self.food.len() < count && self.spawn_food().is_some() {}
+            }
+            FoodPolicy::Timed(max) => {
+                if self.ticks > 0
+                    && self.ticks.is_multiple_of(self.food_interval)
+                    && self.food.len() < max
+                {
+                    self.spawn_food();
+                }
+            }
+        }
+    }
+
+    /// Drops a food item on a tile picked uniformly among the free ones, if any is left.
+    pub fn spawn_food(&mut self) -> Option<Position> {
+        let food_position = self.free.sample(&mut self.rng)?;
+        self.free.remove(food_position);
+        let kind = self.roll_food_kind();
+        self.food.push(FoodItem {
+            position: food_position,
+            kind,
+            expires: self.food_lifetime.map(|lifetime| self.ticks + lifetime),
+        });
+        Some(food_position)
+    }
+
+    fn roll_food_kind(&mut self) -> FoodKind {
+        roll_weighted(&mut self.rng, &self.food_weights).unwrap_or(FoodKind::Normal)
+    }
+
+    /// Drops a power-up on a free tile, if any is left and any kind can be dropped.
+    pub fn spawn_power_up(&mut self) -> Option<Position> {
+        let kind = roll_weighted(&mut self.rng, &self.power_up_weights)?;
+        let position = self.free.sample(&mut self.rng)?;
+        self.free.remove(position);
+        self.power_ups.push(PowerUpItem { position, kind });
+        Some(position)
+    }
+
+    /// Moves food within range of a magnetic head one tile closer to it, when that tile is
+    /// free.
+    fn pull_food(&mut self) {
+        let heads: Vec<Position> = self
+            .snakes
+            .iter()
+            .filter(|snake| snake.alive && snake.has_effect(PowerUpKind::Magnet))
+            .map(Snake::head)
+            .collect();
+        for head in heads {
+            for index in 0..self.food.len() {
+                let position = self.food[index].position;
+                let (dx, dy) = self.arena.offset(position, head);
+                if dx.unsigned_abs() + dy.unsigned_abs() > self.magnet_range {
+                    continue;
+                }
+                let direction = if dx.abs() >= dy.abs() {
+                    if dx > 0 {
+                        Direction::Right
+                    } else {
+                        Direction::Left
+                    }
+                } else if dy > 0 {
+                    Direction::Up
+                } else {
+                    Direction::Down
+                };
+                if let Some(next) = position
+                    .do_move(direction, self.arena)
+                    .filter(|next| self.free.contains(*next))
+                {
+                    self.free.remove(next);
+                    self.food[index].position = next;
+                    self.release(position);
+                }
+            }
+        }
+    }
+
+    /// Multiplier of the tick rate, changed for a while by speed food.
+    pub fn speed_factor(&self) -> f64 {
+        self.speed_effect.map_or(1., |effect| effect.factor)
+    }
+
+    /// Multiplier of the tick rate from the speed ramp, following the leading player, or the
+    /// leading bot in rounds between bots.
+    pub fn ramp_factor(&self) -> f64 {
+        let speed_ramp = match &self.speed_ramp {
+            Some(speed_ramp) => speed_ramp,
+            None => return 1.,
+        };
+        let has_humans = self.snakes.iter().any(|snake| !snake.is_bot());
+        let progress = self
+            .snakes
+            .iter()
+            .filter(|snake| !has_humans || !snake.is_bot())
+            .map(|snake| match speed_ramp.measure {
+                RampMeasure::Score => snake.points,
+                RampMeasure::Length => snake.length(),
+            })
+            .max()
+            .unwrap_or(0);
+        speed_ramp.factor(progress)
+    }
+
+    /// How many times faster than the base move step the round is ticking.
+    pub fn tick_rate_factor(&self) -> f64 {
+        self.ramp_factor() * self.speed_factor()
+    }
+
+    /// Seconds left before a food item disappears, at a given move step.
+    pub fn food_seconds_left(&self, food: &FoodItem, move_step: f64) -> Option<f64> {
+        food.expires
+            .map(|expires| expires.saturating_sub(self.ticks) as f64 * move_step)
+    }
+
+    /// Ticks left of each effect on a snake.
+    pub fn effects_left(&self, snake: &Snake) -> Vec<(PowerUpKind, u64)> {
+        snake
+            .effects
+            .iter()
+            .map(|effect| (effect.kind, effect.until.saturating_sub(self.ticks)))
+            .collect()
+    }
+
+    /// Snakes still in the round, not counting bots.
+    pub fn humans_alive(&self) -> usize {
+        self.snakes
+            .iter()
+            .filter(|snake| snake.alive && !snake.is_bot())
+            .count()
+    }
+
+    /// The round ends once every player is out, when a single snake is left standing, or when
+    /// the board is full. Rounds between bots alone only end by the latter two.
+    pub fn is_over(&self) -> bool {
+        let alive = self.snakes.iter().filter(|snake| snake.alive).count();
+        let has_humans = self.snakes.iter().any(|snake| !snake.is_bot());
+        self.board_full
+            || alive == 0
+            || (self.snakes.len() > 1 && alive == 1)
+            || (has_humans && self.humans_alive() == 0)
+    }
+
+    /// Campaign goals only count the players' progress, not the bots'.
+    pub fn most_eaten(&self) -> u32 {
+        self.snakes
+            .iter()
+            .filter(|snake| !snake.is_bot())
+            .map(|snake| snake.eaten)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn longest(&self) -> u32 {
+        self.snakes
+            .iter()
+            .filter(|snake| !snake.is_bot())
+            .map(Snake::length)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn describe_scores(&self) -> String {
+        if let [snake] = self.snakes.as_slice() {
+            return format!("score {}   length {}", snake.points, snake.length());
+        }
+        self.snakes
+            .iter()
+            .map(|snake| {
+                format!(
+                    "{} {}{}",
+                    snake.name,
+                    snake.points,
+                    if snake.alive { "" } else { " out" }
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("   ")
+    }
+}
+
+/// Picks an item with a chance proportional to its weight, if any weight is positive.
+fn roll_weighted<T: Copy>(rng: &mut StdRng, weights: &[(T, u32)]) -> Option<T> {
+    let total: u32 = weights.iter().map(|(_, weight)| weight).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut roll = rng.gen_range(0..total);
+    for (item, weight) in weights {
+        if roll < *weight {
+            return Some(*item);
+        }
+        roll -= weight;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::Strategy;
+
+    /// A round on a drawn level, with no food or power-ups dropped on their own.
+    fn start(rows: &str, players: usize, boundary: Boundary) -> Simulation {
+        let level = Level::parse("test", rows).unwrap();
+        let config = GameConfig {
+            arena: Arena {
+                width: level.width,
+                height: level.height,
+                boundary,
+            },
+            players,
+            food_policy: FoodPolicy::Timed(1),
+            food_step: 1e9,
+            max_power_ups: 0,
+            speed_ramp: None,
+            ..GameConfig::default()
+        };
+        Simulation::new(&config, &level, &[], 0)
+    }
+
+    fn died(events: &[TickEvent], snake: usize) -> bool {
+        events.contains(&TickEvent::Died { snake })
+    }
+
+    fn unoccupied(simulation: &Simulation) -> usize {
+        (0..simulation.arena.height as i32)
+            .flat_map(|y| (0..simulation.arena.width as i32).map(move |x| Position { x, y }))
+            .filter(|position| !simulation.is_occupied(*position))
+            .count()
+    }
+
+    #[test]
+    fn heads_swapping_tiles_kill_both_snakes() {
+        let mut simulation = start(".><.", 2, Boundary::Walls);
+        let events = simulation.tick(&[]);
+        assert!(died(&events, 0) && died(&events, 1));
+        assert!(simulation.snakes.iter().all(|snake| !snake.alive));
+    }
+
+    #[test]
+    fn head_may_follow_its_own_tail() {
+        let mut simulation = start("vo.\noo.", 1, Boundary::Walls);
+        let events = simulation.tick(&[]);
+        let snake = &simulation.snakes[0];
+        assert!(!died(&events, 0));
+        assert_eq!(snake.head(), Position { x: 0, y: 0 });
+        assert_eq!(snake.length(), 4);
+    }
+
+    #[test]
+    fn walls_kill() {
+        let mut simulation = start(">#.", 1, Boundary::Wrap);
+        let events = simulation.tick(&[]);
+        assert!(died(&events, 0));
+    }
+
+    #[test]
+    fn leaving_the_arena_kills_only_with_walls() {
+        let mut simulation = start("..>", 1, Boundary::Walls);
+        let events = simulation.tick(&[]);
+        assert!(died(&events, 0));
+
+        let mut simulation = start("..>", 1, Boundary::Wrap);
+        let events = simulation.tick(&[]);
+        assert!(!died(&events, 0));
+        assert_eq!(simulation.snakes[0].head(), Position { x: 0, y: 0 });
+    }
+
+    #[test]
+    fn eating_grows_the_snake() {
+        let mut simulation = start(">*..", 1, Boundary::Walls);
+        let events = simulation.tick(&[]);
+        assert!(events.contains(&TickEvent::Ate {
+            snake: 0,
+            position: Position { x: 1, y: 0 },
+            kind: FoodKind::Normal,
+            points: simulation.food_points,
+        }));
+        assert_eq!(simulation.snakes[0].length(), 1);
+        simulation.tick(&[]);
+        assert_eq!(simulation.snakes[0].length(), 2);
+        assert_eq!(simulation.snakes[0].points, simulation.food_points);
+    }
+
+    #[test]
+    fn shrink_food_sheds_tail_segments() {
+        let mut simulation = start("ooo>*.", 1, Boundary::Walls);
+        simulation.food[0].kind = FoodKind::Shrink;
+        simulation.shrink_segments = 2;
+        simulation.tick(&[]);
+        let snake = &simulation.snakes[0];
+        assert!(snake.alive);
+        assert_eq!(snake.length(), 2);
+        assert_eq!(snake.head(), Position { x: 4, y: 0 });
+        assert_eq!(simulation.free.len(), 4);
+    }
+
+    #[test]
+    fn poison_kills() {
+        let mut simulation = start(">*.", 1, Boundary::Walls);
+        simulation.food[0].kind = FoodKind::Poison;
+        let events = simulation.tick(&[]);
+        assert!(died(&events, 0));
+        assert_eq!(simulation.snakes[0].points, 0);
+        assert!(simulation.food.is_empty());
+    }
+
+    #[test]
+    fn constant_policy_keeps_the_food_count() {
+        let mut simulation = start(">.........", 1, Boundary::Walls);
+        simulation.food_policy = FoodPolicy::Constant(3);
+        simulation.replenish_food();
+        assert_eq!(simulation.food.len(), 3);
+        for _ in 0..8 {
+            simulation.tick(&[]);
+            assert_eq!(simulation.food.len(), 3);
+        }
+        assert!(simulation.snakes[0].eaten > 0);
+    }
+
+    #[test]
+    fn timed_policy_drops_one_food_per_interval_up_to_its_max() {
+        let mut simulation = start("v.....\n......\n......\n......", 1, Boundary::Wrap);
+        // Out of the round, so that it eats nothing.
+        simulation.snakes[0].alive = false;
+        simulation.food_policy = FoodPolicy::Timed(2);
+        simulation.food_interval = 3;
+        let counts: Vec<usize> = (0..8)
+            .map(|_| {
+                simulation.tick(&[]);
+                simulation.food.len()
+            })
+            .collect();
+        assert_eq!(counts, [0, 0, 1, 1, 1, 2, 2, 2]);
+    }
+
+    #[test]
+    fn food_disappears_after_its_lifetime() {
+        let mut simulation = start("v.....\n......\n......\n......", 1, Boundary::Wrap);
+        simulation.snakes[0].alive = false;
+        simulation.food_lifetime = Some(2);
+        let position = simulation.spawn_food().unwrap();
+        assert_eq!(simulation.food[0].expires, Some(2));
+        simulation.tick(&[]);
+        assert_eq!(simulation.food.len(), 1);
+        simulation.tick(&[]);
+        assert!(simulation.food.is_empty());
+        assert!(simulation.free.contains(position));
+    }
+
+    #[test]
+    fn power_ups_are_picked_up_and_expire() {
+        let mut simulation = start(">....", 1, Boundary::Walls);
+        let position = Position { x: 1, y: 0 };
+        simulation.free.remove(position);
+        simulation.power_ups.push(PowerUpItem {
+            position,
+            kind: PowerUpKind::Ghost,
+        });
+        simulation.power_up_ticks = 2;
+        let events = simulation.tick(&[]);
+        assert!(events.contains(&TickEvent::PoweredUp {
+            snake: 0,
+            position,
+            kind: PowerUpKind::Ghost,
+        }));
+        assert!(simulation.power_ups.is_empty());
+        assert!(simulation.snakes[0].has_effect(PowerUpKind::Ghost));
+
+        simulation.tick(&[]);
+        assert!(simulation.snakes[0].has_effect(PowerUpKind::Ghost));
+        let events = simulation.tick(&[]);
+        assert!(events.contains(&TickEvent::PowerExpired {
+            snake: 0,
+            kind: PowerUpKind::Ghost,
+        }));
+        assert!(!simulation.snakes[0].has_effect(PowerUpKind::Ghost));
+    }
+
+    /// Steers a long snake around into its own body, returning the events of the tick it gets
+    /// there.
+    fn coil(simulation: &mut Simulation) -> Vec<TickEvent> {
+        simulation.tick(&[toward(Direction::Up)]);
+        simulation.tick(&[toward(Direction::Right)]);
+        simulation.tick(&[toward(Direction::Down)])
+    }
+
+    #[test]
+    fn ghost_passes_through_its_own_body_until_it_expires() {
+        let mut simulation = start(".....\n<oooo\n.....", 1, Boundary::Walls);
+        simulation.snakes[0].give_effect(PowerUpKind::Ghost, 10);
+        let events = coil(&mut simulation);
+        assert!(!died(&events, 0));
+        assert_eq!(simulation.snakes[0].head(), Position { x: 1, y: 1 });
+
+        let mut simulation = start(".....\n<oooo\n.....", 1, Boundary::Walls);
+        simulation.snakes[0].give_effect(PowerUpKind::Ghost, 2);
+        let events = coil(&mut simulation);
+        assert!(died(&events, 0));
+    }
+
+    #[test]
+    fn invincible_survives_walls_and_edges_until_it_expires() {
+        let mut simulation = start(">#..", 1, Boundary::Walls);
+        simulation.snakes[0].give_effect(PowerUpKind::Invincible, 10);
+        let events = simulation.tick(&[]);
+        assert!(!died(&events, 0));
+        assert_eq!(simulation.snakes[0].head(), Position { x: 1, y: 0 });
+
+        let mut simulation = start("..>", 1, Boundary::Walls);
+        simulation.snakes[0].give_effect(PowerUpKind::Invincible, 10);
+        let events = simulation.tick(&[]);
+        assert!(!died(&events, 0));
+        assert_eq!(simulation.snakes[0].head(), Position { x: 0, y: 0 });
+
+        let mut simulation = start(">.#", 1, Boundary::Walls);
+        simulation.snakes[0].give_effect(PowerUpKind::Invincible, 1);
+        simulation.tick(&[]);
+        let events = simulation.tick(&[]);
+        assert!(died(&events, 0));
+    }
+
+    fn food_positions(simulation: &Simulation) -> Vec<i32> {
+        let mut positions: Vec<i32> = simulation.food.iter().map(|food| food.position.x).collect();
+        positions.sort_unstable();
+        positions
+    }
+
+    #[test]
+    fn magnet_pulls_food_within_range_until_it_expires() {
+        let mut simulation = start(">...*...*", 1, Boundary::Walls);
+        simulation.magnet_range = 2;
+        simulation.snakes[0].give_effect(PowerUpKind::Magnet, 10);
+        simulation.tick(&[]);
+        assert_eq!(food_positions(&simulation), [4, 8]);
+        simulation.tick(&[]);
+        assert_eq!(food_positions(&simulation), [3, 8]);
+
+        let mut simulation = start(">...*...*", 1, Boundary::Walls);
+        simulation.magnet_range = 2;
+        simulation.snakes[0].give_effect(PowerUpKind::Magnet, 1);
+        simulation.tick(&[]);
+        simulation.tick(&[]);
+        assert_eq!(food_positions(&simulation), [4, 8]);
+    }
+
+    #[test]
+    fn multiplier_scales_points_until_it_expires() {
+        let mut simulation = start(">*.", 1, Boundary::Walls);
+        simulation.score_multiplier = 3;
+        simulation.snakes[0].give_effect(PowerUpKind::Multiplier, 10);
+        simulation.tick(&[]);
+        assert_eq!(simulation.snakes[0].points, 3 * simulation.food_points);
+
+        let mut simulation = start(">.*", 1, Boundary::Walls);
+        simulation.score_multiplier = 3;
+        simulation.snakes[0].give_effect(PowerUpKind::Multiplier, 1);
+        simulation.tick(&[]);
+        simulation.tick(&[]);
+        assert_eq!(simulation.snakes[0].points, simulation.food_points);
+    }
+
+    #[test]
+    fn reverse_flips_a_players_steering_until_it_expires() {
+        let mut simulation = start(".....\n.>...\n.....", 1, Boundary::Wrap);
+        simulation.snakes[0].give_effect(PowerUpKind::Reverse, 1);
+        simulation.queue(toward(Direction::Up));
+        assert_eq!(simulation.snakes[0].turns, [Direction::Down]);
+        simulation.queue(Turn {
+            snake: 0,
+            steer: Steer::Clockwise,
+        });
+        assert_eq!(
+            simulation.snakes[0].turns,
+            [Direction::Down, Direction::Right]
+        );
+
+        simulation.tick(&[]);
+        simulation.tick(&[]);
+        assert!(!simulation.snakes[0].has_effect(PowerUpKind::Reverse));
+        simulation.queue(toward(Direction::Up));
+        assert_eq!(simulation.snakes[0].turns, [Direction::Up]);
+    }
+
+    #[test]
+    fn reverse_leaves_bots_alone() {
+        let mut simulation = start(".....\n.>...\n.....", 1, Boundary::Wrap);
+        simulation.snakes[0].controller = SnakeController::Bot(Strategy::Greedy);
+        simulation.snakes[0].give_effect(PowerUpKind::Reverse, 10);
+        simulation.queue(toward(Direction::Up));
+        assert_eq!(simulation.snakes[0].turns, [Direction::Up]);
+    }
+
+    fn toward(direction: Direction) -> Turn {
+        Turn {
+            snake: 0,
+            steer: Steer::Toward(direction),
+        }
+    }
+
+    #[test]
+    fn turns_between_two_ticks_are_taken_one_per_tick() {
+        let mut simulation = start(".....\n.>...\n.....", 1, Boundary::Wrap);
+        simulation.tick(&[toward(Direction::Up), toward(Direction::Left)]);
+        let snake = &simulation.snakes[0];
+        assert_eq!(snake.head(), Position { x: 1, y: 2 });
+        assert_eq!(snake.direction, Direction::Up);
+
+        simulation.tick(&[]);
+        let snake = &simulation.snakes[0];
+        assert_eq!(snake.head(), Position { x: 0, y: 2 });
+        assert_eq!(snake.direction, Direction::Left);
+        assert!(snake.turns.is_empty());
+    }
+
+    #[test]
+    fn reversals_are_judged_against_the_last_queued_turn() {
+        let mut simulation = start(".....\n.>...\n.....", 1, Boundary::Wrap);
+        simulation.queue(toward(Direction::Up));
+        simulation.queue(toward(Direction::Down));
+        assert_eq!(simulation.snakes[0].turns, [Direction::Up]);
+
+        simulation.queue(toward(Direction::Left));
+        assert_eq!(simulation.snakes[0].turns, [Direction::Up, Direction::Left]);
+    }
+
+    fn rotate(steer: Steer) -> Turn {
+        Turn { snake: 0, steer }
+    }
+
+    #[test]
+    fn rotations_are_relative_to_the_heading() {
+        let mut simulation = start(".....\n.>...\n.....", 1, Boundary::Wrap);
+        simulation.tick(&[rotate(Steer::Clockwise)]);
+        assert_eq!(simulation.snakes[0].direction, Direction::Down);
+        assert_eq!(simulation.snakes[0].head(), Position { x: 1, y: 0 });
+
+        simulation.tick(&[rotate(Steer::CounterClockwise)]);
+        assert_eq!(simulation.snakes[0].direction, Direction::Right);
+        assert_eq!(simulation.snakes[0].head(), Position { x: 2, y: 0 });
+
+        simulation.tick(&[rotate(Steer::CounterClockwise)]);
+        assert_eq!(simulation.snakes[0].direction, Direction::Up);
+    }
+
+    #[test]
+    fn two_rotations_turn_around_without_reversing() {
+        let mut simulation = start(".....\n.oo>.\n.....", 1, Boundary::Wrap);
+        simulation.queue(toward(Direction::Left));
+        assert!(simulation.snakes[0].turns.is_empty());
+
+        simulation.queue(rotate(Steer::Clockwise));
+        simulation.queue(rotate(Steer::Clockwise));
+        assert_eq!(
+            simulation.snakes[0].turns,
+            [Direction::Down, Direction::Left]
+        );
+        let events = simulation.tick(&[]);
+        assert!(!died(&events, 0));
+        let events = simulation.tick(&[]);
+        assert!(!died(&events, 0));
+        assert_eq!(simulation.snakes[0].head(), Position { x: 2, y: 0 });
+    }
+
+    #[test]
+    fn turns_beyond_the_input_buffer_are_dropped() {
+        let mut simulation = start(".....\n.>...\n.....", 1, Boundary::Wrap);
+        simulation.input_buffer_size = 2;
+        for direction in [Direction::Up, Direction::Left, Direction::Down] {
+            simulation.queue(toward(direction));
+        }
+        assert_eq!(simulation.snakes[0].turns, [Direction::Up, Direction::Left]);
+    }
+
+    #[test]
+    fn board_with_food_left_is_not_full() {
+        let mut simulation = start("..<o", 1, Boundary::Walls);
+        simulation.food_policy = FoodPolicy::Constant(2);
+        simulation.replenish_food();
+        assert_eq!(simulation.food.len(), 2);
+        assert!(simulation.free.is_empty());
+
+        let events = simulation.tick(&[]);
+        assert!(!events.contains(&TickEvent::BoardFull));
+        assert!(!simulation.board_full && !simulation.is_over());
+        assert_eq!(simulation.food.len(), 2);
+    }
+
+    #[test]
+    fn board_the_snake_fills_is_full() {
+        let mut simulation = start("o>*", 1, Boundary::Wrap);
+        let events = simulation.tick(&[]);
+        assert!(!events.contains(&TickEvent::BoardFull));
+
+        let events = simulation.tick(&[]);
+        assert!(events.contains(&TickEvent::BoardFull));
+        assert!(simulation.board_full && simulation.is_over());
+        assert_eq!(simulation.snakes[0].length(), 3);
+    }
+
+    #[test]
+    fn free_cells_track_the_board_until_it_is_full() {
+        let mut simulation = start("*<\noo", 1, Boundary::Wrap);
+        assert_eq!(simulation.free.len(), 0);
+        assert_eq!(simulation.spawn_food(), None);
+
+        simulation.tick(&[]);
+        assert_eq!(simulation.free.len(), 1);
+        assert_eq!(simulation.free.len(), unoccupied(&simulation));
+        assert!(!simulation.board_full);
+
+        let events = simulation.tick(&[Turn {
+            snake: 0,
+            steer: Steer::Toward(Direction::Down),
+        }]);
+        assert!(events.contains(&TickEvent::BoardFull));
+        assert!(simulation.board_full && simulation.is_over());
+        assert_eq!(simulation.free.len(), 0);
+        assert_eq!(simulation.spawn_food(), None);
+
+        let events = simulation.tick(&[]);
+        assert!(died(&events, 0));
+        assert_eq!(simulation.free.len(), 4);
+        assert_eq!(simulation.free.len(), unoccupied(&simulation));
+    }
+}